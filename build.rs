@@ -4,5 +4,18 @@
 /// See: https://github.com/hyperium/tonic/blob/master/examples/helloworld-tutorial.md#generating-server-and-client-code
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("proto/statservice.proto")?;
+    tonic_build::compile_protos("proto/statservice/v2/statservice.proto")?;
+
+    // Error details of the gRPC error model, used in the status of failed calls
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .compile(
+            &[
+                "proto/google/rpc/status.proto",
+                "proto/google/rpc/error_details.proto",
+            ],
+            &["proto"],
+        )?;
     Ok(())
 }
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rs_distributed_stats::client_log::{read_client_log, ClientLogRecord};
+use rs_distributed_stats::plot::{box_plot_svg, cdf_svg, Series};
+use rs_distributed_stats::stats::Summary;
+
+/// Gets a timing of a logged request.
+type Metric = fn(&ClientLogRecord) -> u64;
+
+/// Timings shown in the charts and tables, with the name used in titles and file names.
+const METRICS: [(&str, Metric); 4] = [
+    ("Turnaround", |record| record.turnaround_ms),
+    ("Execution", |record| record.execution_ms),
+    ("Waiting", |record| record.waiting_ms),
+    ("Network", |record| record.network_ms),
+];
+
+/// Number of metrics drawn as charts. The network time is only given in the tables.
+const CHARTED_METRICS: usize = 3;
+
+/// Options of the analysis given as command-line arguments.
+struct AnalyzeOptions {
+    /// Directory with the client logs, such as `log` or a results directory of `simulate`.
+    log_dir: PathBuf,
+    /// Directory the report and the charts are written to.
+    output: PathBuf,
+}
+
+/// Requests of a zone or an RPC.
+#[derive(Default)]
+struct Group<'a> {
+    requests: usize,
+    succeeded: usize,
+    cached: usize,
+    failed: usize,
+    /// Successful requests answered by a server, which have timings.
+    timed: Vec<&'a ClientLogRecord>,
+}
+
+impl<'a> Group<'a> {
+    fn add(&mut self, record: &'a ClientLogRecord) {
+        self.requests += 1;
+        if record.status_code != "Ok" {
+            self.failed += 1;
+        } else if record.cache_status == "client" {
+            self.succeeded += 1;
+            self.cached += 1;
+        } else {
+            self.succeeded += 1;
+            self.timed.push(record);
+        }
+    }
+
+    fn values(&self, metric: Metric) -> Vec<u64> {
+        self.timed.iter().map(|record| metric(record)).collect()
+    }
+}
+
+/// Find the client logs `client_data_z<ZONE>.csv` in the directory, ordered by zone.
+fn find_client_logs(dir: &Path) -> Result<Vec<(i32, PathBuf)>, Box<dyn Error>> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read log directory {}: {}", dir.display(), e))?;
+
+    let mut logs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let zone = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("client_data_z"))
+            .and_then(|name| name.strip_suffix(".csv"))
+            .and_then(|zone| zone.parse::<i32>().ok());
+        if let Some(zone) = zone {
+            logs.push((zone, path));
+        }
+    }
+
+    logs.sort();
+    Ok(logs)
+}
+
+/// Group the records by a key, in the order of the key.
+fn group_by<'a, K: Ord>(
+    records: &'a [ClientLogRecord],
+    key: impl Fn(&ClientLogRecord) -> K,
+) -> BTreeMap<K, Group<'a>> {
+    let mut groups: BTreeMap<K, Group> = BTreeMap::new();
+    for record in records {
+        groups.entry(key(record)).or_default().add(record);
+    }
+    groups
+}
+
+/// Write the request counts and the timing distributions of the groups as Markdown tables,
+/// and a box plot of each charted metric. CDFs are drawn as well if `cdf` is set.
+///
+/// The charts are written to the output directory and linked from the report.
+fn write_section(
+    report: &mut String,
+    output: &Path,
+    heading: &str,
+    slug: &str,
+    groups: &[(String, Group)],
+    cdf: bool,
+) -> Result<(), Box<dyn Error>> {
+    let _ = writeln!(report, "## By {}\n", heading);
+    let _ = writeln!(
+        report,
+        "| {} | Requests | Succeeded | Client cache | Failed |",
+        capitalize(heading)
+    );
+    let _ = writeln!(report, "|---|---:|---:|---:|---:|");
+    for (name, group) in groups {
+        let _ = writeln!(
+            report,
+            "| {} | {} | {} | {} | {} |",
+            name, group.requests, group.succeeded, group.cached, group.failed
+        );
+    }
+    let _ = writeln!(report);
+
+    for (index, (metric_name, metric)) in METRICS.iter().enumerate() {
+        let values: Vec<(&String, Vec<u64>)> = groups
+            .iter()
+            .map(|(name, group)| (name, group.values(*metric)))
+            .collect();
+
+        let _ = writeln!(report, "### {} time (ms)\n", metric_name);
+        let _ = writeln!(
+            report,
+            "| {} | Count | Min | Mean | p50 | p95 | p99 | Max |",
+            capitalize(heading)
+        );
+        let _ = writeln!(report, "|---|---:|---:|---:|---:|---:|---:|---:|");
+        for (name, values) in &values {
+            if let Some(summary) = Summary::from_values(values) {
+                let _ = writeln!(
+                    report,
+                    "| {} | {} | {} | {:.1} | {} | {} | {} | {} |",
+                    name,
+                    summary.count,
+                    summary.min,
+                    summary.mean,
+                    summary.p50,
+                    summary.p95,
+                    summary.p99,
+                    summary.max
+                );
+            }
+        }
+        let _ = writeln!(report);
+
+        if index >= CHARTED_METRICS {
+            continue;
+        }
+
+        let series: Vec<Series> = values
+            .iter()
+            .map(|(name, values)| Series {
+                name: name.to_string(),
+                values,
+            })
+            .collect();
+        let file_name = format!("{}_by_{}.svg", metric_name.to_lowercase(), slug);
+        let title = format!("{} time by {}", metric_name, heading);
+        fs::write(
+            output.join(&file_name),
+            box_plot_svg(&title, "Time (ms)", &series),
+        )?;
+        let _ = writeln!(report, "![{}]({})\n", title, file_name);
+
+        if cdf {
+            let file_name = format!("{}_cdf_by_{}.svg", metric_name.to_lowercase(), slug);
+            let title = format!(
+                "CDF of the {} time by {}",
+                metric_name.to_lowercase(),
+                heading
+            );
+            fs::write(
+                output.join(&file_name),
+                cdf_svg(&title, "Time (ms)", &series),
+            )?;
+            let _ = writeln!(report, "![{}]({})\n", title, file_name);
+        }
+    }
+
+    Ok(())
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Parse the optional analysis arguments.
+///
+/// The first argument is the directory with the client logs, `log` by default.
+/// `--output <dir>` writes the report and the charts to the given directory instead of `<log dir>/analysis`.
+fn parse_options(args: &[String]) -> Result<AnalyzeOptions, Box<dyn Error>> {
+    let mut log_dir: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") && log_dir.is_none() {
+            log_dir = Some(PathBuf::from(arg));
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", arg))?;
+
+        match arg.as_str() {
+            "--output" => output = Some(PathBuf::from(value)),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    let log_dir = log_dir.unwrap_or_else(|| PathBuf::from("log"));
+    Ok(AnalyzeOptions {
+        output: output.unwrap_or_else(|| log_dir.join("analysis")),
+        log_dir,
+    })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--help") {
+        eprintln!("Usage: {} [Log directory] [--output <dir>]", args[0]);
+        return Ok(());
+    }
+    let options = parse_options(&args[1..])?;
+
+    // Read the log of every client
+    let logs = find_client_logs(&options.log_dir)?;
+    if logs.is_empty() {
+        return Err(format!("No client logs found in {}", options.log_dir.display()).into());
+    }
+    let mut records = Vec::new();
+    for (zone, path) in &logs {
+        let log = read_client_log(path, *zone)?;
+        println!("[INFO] Read {} requests from {}", log.len(), path.display());
+        records.extend(log);
+    }
+
+    fs::create_dir_all(&options.output).map_err(|e| {
+        format!(
+            "Failed to create output directory {}: {}",
+            options.output.display(),
+            e
+        )
+    })?;
+
+    // Summary of all requests, and the failures per status code and error reason
+    let all = group_by(&records, |_| ());
+    let all = &all[&()];
+    let mut report = String::new();
+    let _ = writeln!(report, "# Simulation report\n");
+    let _ = writeln!(
+        report,
+        "{} requests from {} clients in `{}`: {} succeeded ({} from the client cache) and {} failed.\n",
+        all.requests,
+        logs.len(),
+        options.log_dir.display(),
+        all.succeeded,
+        all.cached,
+        all.failed
+    );
+    let _ = writeln!(
+        report,
+        "Timings are in milliseconds, and only include successful requests answered by a server.\n"
+    );
+
+    let failures = group_by(&records, |record| {
+        (record.status_code.clone(), record.error_reason.clone())
+    });
+    if all.failed > 0 {
+        let _ = writeln!(report, "## Failures\n");
+        let _ = writeln!(report, "| Status | Reason | Requests |");
+        let _ = writeln!(report, "|---|---|---:|");
+        for ((code, reason), group) in failures.iter().filter(|((code, _), _)| code != "Ok") {
+            let _ = writeln!(
+                report,
+                "| {} | {} | {} |",
+                code,
+                reason.as_deref().unwrap_or("-"),
+                group.requests
+            );
+        }
+        let _ = writeln!(report);
+    }
+
+    // Distributions per client zone and per RPC
+    let by_zone: Vec<(String, Group)> = group_by(&records, |record| record.client_zone)
+        .into_iter()
+        .map(|(zone, group)| (format!("Zone {}", zone), group))
+        .collect();
+    write_section(
+        &mut report,
+        &options.output,
+        "client zone",
+        "zone",
+        &by_zone,
+        true,
+    )?;
+
+    let by_rpc: Vec<(String, Group)> = group_by(&records, |record| match record.rpc.as_str() {
+        "" => String::from("unknown"),
+        rpc => rpc.to_string(),
+    })
+    .into_iter()
+    .collect();
+    write_section(&mut report, &options.output, "RPC", "rpc", &by_rpc, false)?;
+
+    let report_path = options.output.join("report.md");
+    fs::write(&report_path, report)?;
+    println!("[INFO] Report written to {}", report_path.display());
+
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Exp};
+
+/// Target request rate over time of an open-loop client, in requests per second.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrivalProfile {
+    /// The same rate for the whole run.
+    Poisson { rps: f64 },
+    /// A sequence of rates, each held for the given time. The last rate is held until the end of the run.
+    Step { steps: Vec<(f64, Duration)> },
+    /// A linear change from one rate to another over the given time, then held at the final rate.
+    Ramp { from: f64, to: f64, over: Duration },
+}
+
+impl FromStr for ArrivalProfile {
+    type Err = String;
+
+    /// Parse a profile from `poisson:<rps>`, `step:<rps>:<seconds>,<rps>:<seconds>,...` or `ramp:<from rps>:<to rps>:<seconds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid arrival profile: {}", s);
+        let number = |value: &str| value.parse::<f64>().map_err(|_| invalid());
+        let seconds = |value: &str| {
+            number(value).and_then(|secs| Duration::try_from_secs_f64(secs).map_err(|_| invalid()))
+        };
+
+        let (kind, parameters) = s.split_once(':').ok_or_else(invalid)?;
+        let profile = match kind.to_lowercase().as_str() {
+            "poisson" => ArrivalProfile::Poisson {
+                rps: number(parameters)?,
+            },
+            "step" => {
+                let steps = parameters
+                    .split(',')
+                    .map(|step| {
+                        let (rps, secs) = step.split_once(':').ok_or_else(invalid)?;
+                        Ok((number(rps)?, seconds(secs)?))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                ArrivalProfile::Step { steps }
+            }
+            "ramp" => match parameters.split(':').collect::<Vec<_>>()[..] {
+                [from, to, over] => ArrivalProfile::Ramp {
+                    from: number(from)?,
+                    to: number(to)?,
+                    over: seconds(over)?,
+                },
+                _ => return Err(invalid()),
+            },
+            _ => return Err(format!("Unknown arrival profile: {}", s)),
+        };
+
+        profile.validate()?;
+        Ok(profile)
+    }
+}
+
+impl fmt::Display for ArrivalProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArrivalProfile::Poisson { rps } => write!(f, "Poisson at {} rps", rps),
+            ArrivalProfile::Step { steps } => {
+                let steps: Vec<String> = steps
+                    .iter()
+                    .map(|(rps, secs)| format!("{} rps for {} s", rps, secs.as_secs_f64()))
+                    .collect();
+                write!(f, "steps of {}", steps.join(", then "))
+            }
+            ArrivalProfile::Ramp { from, to, over } => write!(
+                f,
+                "ramp from {} to {} rps over {} s",
+                from,
+                to,
+                over.as_secs_f64()
+            ),
+        }
+    }
+}
+
+impl ArrivalProfile {
+    /// Check that every rate is finite and not negative, and that at least one is positive.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self
+            .rates()
+            .iter()
+            .all(|rate| rate.is_finite() && *rate >= 0.0)
+        {
+            return Err(format!("Arrival profile has an invalid rate: {}", self));
+        }
+        if self.max_rate() <= 0.0 {
+            return Err(format!("Arrival profile has no positive rate: {}", self));
+        }
+        Ok(())
+    }
+
+    /// Every rate given in the profile.
+    fn rates(&self) -> Vec<f64> {
+        match self {
+            ArrivalProfile::Poisson { rps } => vec![*rps],
+            ArrivalProfile::Step { steps } => steps.iter().map(|(rps, _)| *rps).collect(),
+            ArrivalProfile::Ramp { from, to, .. } => vec![*from, *to],
+        }
+    }
+
+    /// Rate held after the end of the profile.
+    fn final_rate(&self) -> f64 {
+        match self {
+            ArrivalProfile::Poisson { rps } => *rps,
+            ArrivalProfile::Step { steps } => steps.last().map_or(0.0, |(rps, _)| *rps),
+            ArrivalProfile::Ramp { to, .. } => *to,
+        }
+    }
+
+    /// Time after which the rate no longer changes.
+    fn end(&self) -> Duration {
+        match self {
+            ArrivalProfile::Poisson { .. } => Duration::ZERO,
+            ArrivalProfile::Step { steps } => steps.iter().map(|(_, length)| *length).sum(),
+            ArrivalProfile::Ramp { over, .. } => *over,
+        }
+    }
+
+    /// Highest rate of the profile.
+    fn max_rate(&self) -> f64 {
+        self.rates().into_iter().fold(0.0, f64::max)
+    }
+
+    /// Target rate at the given time since the start of the run.
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        match self {
+            ArrivalProfile::Poisson { rps } => *rps,
+            ArrivalProfile::Step { steps } => {
+                let mut end = Duration::ZERO;
+                for (rps, length) in steps {
+                    end += *length;
+                    if elapsed < end {
+                        return *rps;
+                    }
+                }
+                steps.last().map_or(0.0, |(rps, _)| *rps)
+            }
+            ArrivalProfile::Ramp { from, to, over } => {
+                if elapsed >= *over || over.is_zero() {
+                    *to
+                } else {
+                    from + (to - from) * elapsed.as_secs_f64() / over.as_secs_f64()
+                }
+            }
+        }
+    }
+}
+
+/// Send times of the requests of an open-loop client, as offsets from the start of the run.
+///
+/// Arrivals are a Poisson process following the rate of the profile,
+/// sampled by thinning a process at the highest rate. The times only depend on the profile and the seed.
+pub struct ArrivalSchedule {
+    profile: ArrivalProfile,
+    rng: StdRng,
+    gaps: Exp<f64>,
+    max_rate: f64,
+    elapsed: f64,
+}
+
+impl ArrivalSchedule {
+    /// Create the schedule of a profile. Fails if the profile is not valid.
+    pub fn new(profile: ArrivalProfile, seed: u64) -> Result<Self, String> {
+        profile.validate()?;
+        let max_rate = profile.max_rate();
+        Ok(ArrivalSchedule {
+            gaps: Exp::new(max_rate).map_err(|e| format!("Invalid arrival rate: {}", e))?,
+            profile,
+            rng: StdRng::seed_from_u64(seed),
+            max_rate,
+            elapsed: 0.0,
+        })
+    }
+}
+
+impl Iterator for ArrivalSchedule {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        loop {
+            self.elapsed += self.gaps.sample(&mut self.rng);
+            // No more arrivals once the time no longer fits in a duration
+            let offset = Duration::try_from_secs_f64(self.elapsed).ok()?;
+
+            // No more arrivals once the profile has ended with a rate of zero
+            if offset >= self.profile.end() && self.profile.final_rate() == 0.0 {
+                return None;
+            }
+
+            // Keep the arrival with the probability of the target rate at that time
+            let rate = self.profile.rate_at(offset);
+            if self.rng.gen::<f64>() * self.max_rate < rate {
+                return Some(offset);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrivals(profile: &str, seed: u64, until: Duration) -> Vec<Duration> {
+        ArrivalSchedule::new(profile.parse().unwrap(), seed)
+            .unwrap()
+            .take_while(|offset| *offset < until)
+            .collect()
+    }
+
+    #[test]
+    fn parses_profiles() {
+        assert_eq!(
+            "poisson:20".parse(),
+            Ok(ArrivalProfile::Poisson { rps: 20.0 })
+        );
+        assert_eq!(
+            "step:10:5,0:2.5".parse(),
+            Ok(ArrivalProfile::Step {
+                steps: vec![
+                    (10.0, Duration::from_secs(5)),
+                    (0.0, Duration::from_millis(2500))
+                ]
+            })
+        );
+        assert_eq!(
+            "ramp:1:100:60".parse(),
+            Ok(ArrivalProfile::Ramp {
+                from: 1.0,
+                to: 100.0,
+                over: Duration::from_secs(60)
+            })
+        );
+        assert!("poisson:-1".parse::<ArrivalProfile>().is_err());
+        assert!("step:0:5,0:5".parse::<ArrivalProfile>().is_err());
+        assert!("ramp:1:2".parse::<ArrivalProfile>().is_err());
+        assert!("burst:5".parse::<ArrivalProfile>().is_err());
+    }
+
+    #[test]
+    fn rejects_profiles_without_a_positive_rate() {
+        let profile = ArrivalProfile::Step {
+            steps: vec![(0.0, Duration::from_secs(1))],
+        };
+
+        assert!(ArrivalSchedule::new(profile, 1).is_err());
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_arrivals() {
+        let until = Duration::from_secs(10);
+
+        assert_eq!(
+            arrivals("ramp:5:50:10", 3, until),
+            arrivals("ramp:5:50:10", 3, until)
+        );
+        assert_ne!(
+            arrivals("ramp:5:50:10", 3, until),
+            arrivals("ramp:5:50:10", 4, until)
+        );
+    }
+
+    #[test]
+    fn arrivals_follow_the_rate() {
+        // 100 rps over 100 s gives about 10000 arrivals
+        let count = arrivals("poisson:100", 9, Duration::from_secs(100)).len();
+        assert!((9500..10500).contains(&count), "{} arrivals", count);
+
+        // Thinning keeps about a tenth of the arrivals in the slow step
+        let steps = arrivals("step:100:10,10:10", 9, Duration::from_secs(20));
+        let fast = steps
+            .iter()
+            .filter(|offset| **offset < Duration::from_secs(10))
+            .count();
+        let slow = steps.len() - fast;
+        assert!((900..1100).contains(&fast), "{} fast arrivals", fast);
+        assert!((60..140).contains(&slow), "{} slow arrivals", slow);
+    }
+
+    #[test]
+    fn arrivals_end_with_a_final_rate_of_zero() {
+        let schedule = ArrivalSchedule::new("step:50:2,0:1".parse().unwrap(), 1).unwrap();
+        let offsets: Vec<Duration> = schedule.collect();
+
+        assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(offsets
+            .iter()
+            .all(|offset| *offset < Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn ramps_interpolate_the_rate() {
+        let ramp: ArrivalProfile = "ramp:10:30:20".parse().unwrap();
+
+        assert_eq!(ramp.rate_at(Duration::ZERO), 10.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(10)), 20.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(60)), 30.0);
+    }
+}
@@ -0,0 +1,288 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Policy used to decide which entry is removed when the cache is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry.
+    Lru,
+    /// Evict the least frequently used entry.
+    Lfu,
+    /// Entries expire after the given duration. When full, the oldest entry is evicted.
+    Ttl(Duration),
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    /// Parse a policy from `lru`, `lfu` or `ttl:<seconds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "lfu" => Ok(EvictionPolicy::Lfu),
+            other => match other.strip_prefix("ttl:") {
+                Some(secs) => secs
+                    .parse::<u64>()
+                    .map(|secs| EvictionPolicy::Ttl(Duration::from_secs(secs)))
+                    .map_err(|_| format!("Invalid TTL in cache policy: {}", s)),
+                None => Err(format!("Unknown cache policy: {}", s)),
+            },
+        }
+    }
+}
+
+/// A single value in the cache with the data needed by the eviction policies.
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    last_used: u64,
+    uses: u64,
+}
+
+/// Bounded key value cache with a configurable eviction policy.
+///
+/// Eviction scans all entries, so the cache is meant for small capacities.
+pub struct Cache<K, V> {
+    capacity: usize,
+    policy: EvictionPolicy,
+    entries: HashMap<K, Entry<V>>,
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Cache<K, V> {
+    /// Create an empty cache holding at most `capacity` entries.
+    pub fn new(capacity: usize, policy: EvictionPolicy) -> Self {
+        Cache {
+            capacity,
+            policy,
+            entries: HashMap::with_capacity(capacity),
+            clock: 0,
+        }
+    }
+
+    /// Get a copy of the cached value for the key.
+    ///
+    /// Expired entries are removed and reported as a miss.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let EvictionPolicy::Ttl(ttl) = self.policy {
+            if self
+                .entries
+                .get(key)
+                .is_some_and(|entry| entry.inserted.elapsed() > ttl)
+            {
+                self.entries.remove(key);
+                return None;
+            }
+        }
+
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        entry.uses += 1;
+        Some(entry.value.clone())
+    }
+
+    /// Insert a value, evicting an entry first if the cache is full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.evict();
+        }
+
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                inserted: Instant::now(),
+                last_used: self.clock,
+                uses: 1,
+            },
+        );
+    }
+
+    /// Remove the entry for the key.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.remove(key).map(|entry| entry.value)
+    }
+
+    /// Keep only the entries for which the predicate returns true.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.entries.retain(|key, entry| keep(key, &entry.value));
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove one entry chosen by the eviction policy.
+    fn evict(&mut self) {
+        let victim = match self.policy {
+            EvictionPolicy::Lru => self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone()),
+            EvictionPolicy::Lfu => self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| (entry.uses, entry.last_used))
+                .map(|(key, _)| key.clone()),
+            EvictionPolicy::Ttl(_) => self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.inserted)
+                .map(|(key, _)| key.clone()),
+        };
+
+        if let Some(key) = victim {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_cache(policy: EvictionPolicy) -> Cache<&'static str, u32> {
+        let mut cache = Cache::new(3, policy);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        cache
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used_entry() {
+        let mut cache = full_cache(EvictionPolicy::Lru);
+        cache.get("a");
+        cache.get("c");
+
+        cache.insert("d", 4);
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("d"), Some(4));
+    }
+
+    #[test]
+    fn lfu_evicts_the_least_frequently_used_entry() {
+        let mut cache = full_cache(EvictionPolicy::Lfu);
+        cache.get("a");
+        cache.get("a");
+        cache.get("b");
+        cache.get("b");
+        // "c" is used last, but the least often
+        cache.get("c");
+
+        cache.insert("d", 4);
+
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), Some(2));
+    }
+
+    #[test]
+    fn lfu_breaks_ties_by_recency() {
+        let mut cache = full_cache(EvictionPolicy::Lfu);
+        cache.get("c");
+        cache.get("a");
+        cache.get("b");
+
+        cache.insert("d", 4);
+
+        assert_eq!(cache.get("c"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ttl_expires_entries() {
+        let mut cache = Cache::new(3, EvictionPolicy::Ttl(Duration::from_secs(10)));
+        cache.insert("a", 1);
+        tokio::time::advance(Duration::from_secs(5)).await;
+        cache.insert("b", 2);
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(2));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ttl_evicts_the_oldest_entry_when_full() {
+        let mut cache = Cache::new(2, EvictionPolicy::Ttl(Duration::from_secs(60)));
+        cache.insert("a", 1);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        cache.insert("b", 2);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        cache.get("a");
+
+        cache.insert("c", 3);
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(2));
+    }
+
+    #[test]
+    fn replacing_a_key_does_not_evict() {
+        let mut cache = full_cache(EvictionPolicy::Lru);
+
+        cache.insert("a", 10);
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get("a"), Some(10));
+        assert_eq!(cache.get("b"), Some(2));
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let mut cache = Cache::new(0, EvictionPolicy::Lru);
+        cache.insert("a", 1);
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!("LRU".parse(), Ok(EvictionPolicy::Lru));
+        assert_eq!("lfu".parse(), Ok(EvictionPolicy::Lfu));
+        assert_eq!(
+            "ttl:30".parse(),
+            Ok(EvictionPolicy::Ttl(Duration::from_secs(30)))
+        );
+        assert!("ttl:soon".parse::<EvictionPolicy>().is_err());
+        assert!("fifo".parse::<EvictionPolicy>().is_err());
+    }
+}
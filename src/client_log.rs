@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use csv::{ReaderBuilder, Writer, WriterBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Latest version of the client log schema, written in the `version` column of every row.
+///
+/// Version 1 was the headerless log with only the turnaround, execution and waiting times.
+/// Version 2 named the send time column `timestamp_ms`, although it was never a Unix timestamp.
+pub const CLIENT_LOG_VERSION: u32 = 3;
+
+/// A finished request in the client log `log/client_data_z<ZONE>.csv`.
+///
+/// The file starts with a header row naming the columns, in the order of the fields.
+/// Times are in milliseconds, and failed requests have no result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientLogRecord {
+    /// Version of the log schema.
+    pub version: u32,
+    /// Time the request was sent, in milliseconds after the client started sending requests.
+    #[serde(alias = "timestamp_ms")]
+    pub sent_offset_ms: u64,
+    /// Id of the request from the request file.
+    pub request_id: String,
+    /// Function name of the request, e.g. `getNumberofCities`.
+    pub rpc: String,
+    /// Parameters of the request separated by spaces, as in the text request format.
+    pub parameters: String,
+    pub client_zone: i32,
+    /// Zone the request was sent to.
+    pub target_zone: Option<u32>,
+    /// Server that handled the request. The server columns are empty for requests answered from the client cache or that failed.
+    pub server_id: Option<u32>,
+    pub server_zone: Option<u32>,
+    /// Requests in flight on the server when it responded, including this one.
+    pub server_in_flight: Option<u64>,
+    /// Number of requests the server received before this one.
+    pub server_sequence: Option<u64>,
+    /// `hit` or `miss` in the server cache, `none` without a server cache, or `client` if answered from the client cache.
+    pub cache_status: String,
+    /// gRPC status code of the call, e.g. `Ok` or `Unavailable`.
+    pub status_code: String,
+    /// Reason from the `google.rpc.ErrorInfo` detail of a failed request, e.g. `COUNTRY_NOT_FOUND`.
+    pub error_reason: Option<String>,
+    pub result: Option<i64>,
+    pub turnaround_ms: u64,
+    pub execution_ms: u64,
+    pub waiting_ms: u64,
+    /// Simulated network delay included in the turnaround time.
+    pub network_ms: u64,
+}
+
+/// Writes the records of a client to its log file, replacing the log of an earlier run.
+pub struct ClientLogWriter {
+    writer: Writer<File>,
+}
+
+impl ClientLogWriter {
+    /// Create the log file of the client zone in the `log` directory.
+    pub fn create(client_zone: i32) -> Result<Self, Box<dyn Error>> {
+        Self::create_at(&client_log_path(client_zone))
+    }
+
+    /// Create a log file at the given path, creating the directory if needed.
+    pub fn create_at(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create file {}: {}", path.display(), e))?;
+        let writer = WriterBuilder::new().has_headers(true).from_writer(file);
+        Ok(ClientLogWriter { writer })
+    }
+
+    /// Write a record and flush it, so the log is complete if the client is stopped.
+    pub fn write(&mut self, record: &ClientLogRecord) -> Result<(), Box<dyn Error>> {
+        self.writer.serialize(record)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read the records of a client log of any version.
+///
+/// Version 1 logs only have the turnaround, execution and waiting times, so the other columns are left empty,
+/// the cache status is `none` and the client zone is taken from the given zone.
+pub fn read_client_log(
+    path: &Path,
+    client_zone: i32,
+) -> Result<Vec<ClientLogRecord>, Box<dyn Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("Failed to open file {}: {}", path.display(), e))?;
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file);
+
+    let mut rows = reader.records();
+    let first = match rows.next() {
+        Some(row) => row?,
+        None => return Ok(Vec::new()),
+    };
+
+    // Versioned logs start with a header row
+    if first.get(0) == Some("version") {
+        let mut records = Vec::new();
+        for row in rows {
+            let record: ClientLogRecord = row?
+                .deserialize(Some(&first))
+                .map_err(|e| format!("Invalid record in client log {}: {}", path.display(), e))?;
+            records.push(record);
+        }
+        return Ok(records);
+    }
+
+    let mut records = Vec::new();
+    for row in std::iter::once(Ok(first)).chain(rows) {
+        let row = row?;
+        let column = |index: usize| -> Result<u64, Box<dyn Error>> {
+            match row.get(index) {
+                Some(value) => Ok(value.trim().parse::<u64>().map_err(|_| {
+                    format!("Invalid time in client log {}: {}", path.display(), value)
+                })?),
+                None => Ok(0),
+            }
+        };
+
+        records.push(ClientLogRecord {
+            version: 1,
+            sent_offset_ms: 0,
+            request_id: String::new(),
+            rpc: String::new(),
+            parameters: String::new(),
+            client_zone,
+            target_zone: None,
+            server_id: None,
+            server_zone: None,
+            server_in_flight: None,
+            server_sequence: None,
+            cache_status: String::from("none"),
+            status_code: String::from("Ok"),
+            error_reason: None,
+            result: None,
+            turnaround_ms: column(0)?,
+            execution_ms: column(1)?,
+            waiting_ms: column(2)?,
+            network_ms: 0,
+        });
+    }
+    Ok(records)
+}
+
+/// Path of the log of the client in the given zone: `log/client_data_z<ZONE>.csv`.
+pub fn client_log_path(client_zone: i32) -> PathBuf {
+    Path::new("log").join(format!("client_data_z{}.csv", client_zone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(request_id: &str) -> ClientLogRecord {
+        ClientLogRecord {
+            version: CLIENT_LOG_VERSION,
+            sent_offset_ms: 5,
+            request_id: request_id.to_string(),
+            rpc: String::from("getNumberofCities"),
+            parameters: String::from("Norway 5000"),
+            client_zone: 2,
+            target_zone: Some(3),
+            server_id: Some(3),
+            server_zone: Some(3),
+            server_in_flight: Some(1),
+            server_sequence: Some(0),
+            cache_status: String::from("miss"),
+            status_code: String::from("Ok"),
+            error_reason: None,
+            result: Some(12),
+            turnaround_ms: 190,
+            execution_ms: 4,
+            waiting_ms: 16,
+            network_ms: 170,
+        }
+    }
+
+    /// A path in the temporary directory, unique to the test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("client_log_{}_{}.csv", name, std::process::id()))
+    }
+
+    #[test]
+    fn reads_back_written_records() {
+        let path = temp_path("round_trip");
+        let mut failed = record("b");
+        failed.status_code = String::from("NotFound");
+        failed.error_reason = Some(String::from("COUNTRY_NOT_FOUND"));
+        failed.result = None;
+        failed.server_id = None;
+
+        let mut writer = ClientLogWriter::create_at(&path).unwrap();
+        writer.write(&record("a")).unwrap();
+        writer.write(&failed).unwrap();
+        drop(writer);
+
+        let records = read_client_log(&path, 9).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records, [record("a"), failed]);
+    }
+
+    #[test]
+    fn reads_version_2_logs() {
+        let path = temp_path("version_2");
+        fs::write(
+            &path,
+            "version,timestamp_ms,request_id,rpc,parameters,client_zone,target_zone,server_id,server_zone,\
+             server_in_flight,server_sequence,cache_status,status_code,error_reason,result,\
+             turnaround_ms,execution_ms,waiting_ms,network_ms\n\
+             2,5,a,getNumberofCities,Norway 5000,2,3,3,3,1,0,miss,Ok,,12,190,4,16,170\n",
+        )
+        .unwrap();
+
+        let records = read_client_log(&path, 9).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut expected = record("a");
+        expected.version = 2;
+        assert_eq!(records, [expected]);
+    }
+
+    #[test]
+    fn reads_version_1_logs() {
+        let path = temp_path("version_1");
+        fs::write(&path, "190,4,16\n210,5,20\n").unwrap();
+
+        let records = read_client_log(&path, 4).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].version, 1);
+        assert_eq!(records[1].client_zone, 4);
+        assert_eq!(records[1].turnaround_ms, 210);
+        assert_eq!(records[1].execution_ms, 5);
+        assert_eq!(records[1].waiting_ms, 20);
+        assert_eq!(records[1].network_ms, 0);
+        assert_eq!(records[1].cache_status, "none");
+        assert_eq!(records[1].status_code, "Ok");
+    }
+
+    #[test]
+    fn rejects_invalid_version_1_times() {
+        let path = temp_path("invalid");
+        fs::write(&path, "190,fast,16\n").unwrap();
+
+        let result = read_client_log(&path, 1);
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn reads_empty_logs() {
+        let path = temp_path("empty");
+        fs::write(&path, "").unwrap();
+
+        let records = read_client_log(&path, 1).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(records.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection, Row};
+
+/// Path of the database used by `StatServer`.
+pub const DEFAULT_DATABASE_PATH: &str = "db/city_database.db";
+
+/// Schema of the `cities` table.
+///
+/// The column names follow the geonames CSV export, which the server queries.
+const SCHEMA: &str = "
+CREATE TABLE cities (
+    [Geoname ID] INTEGER PRIMARY KEY,
+    [Name] TEXT NOT NULL,
+    [ASCII Name] TEXT NOT NULL,
+    [Country Code] TEXT NOT NULL,
+    [Country name EN] TEXT NOT NULL,
+    [Admin1 Code] TEXT,
+    [Population] INTEGER NOT NULL CHECK ([Population] >= 0),
+    [Elevation] INTEGER,
+    [Timezone] TEXT,
+    [Latitude] REAL NOT NULL CHECK ([Latitude] BETWEEN -90 AND 90),
+    [Longitude] REAL NOT NULL CHECK ([Longitude] BETWEEN -180 AND 180)
+)";
+
+/// Indexes used by the server queries, created after the rows are inserted.
+const INDEXES: &str = "
+CREATE INDEX cities_country ON cities ([Country name EN], [Population]);
+CREATE INDEX cities_population ON cities ([Population]);
+";
+
+/// Query selecting every column of the `cities` table, in the order read by `City::from_row`.
+///
+/// Filters and ordering are appended by the caller.
+pub const SELECT_CITIES: &str = "SELECT [Geoname ID], [Name], [ASCII Name], [Country Code], [Country name EN], [Admin1 Code], [Population], [Elevation], [Timezone], [Latitude], [Longitude] FROM cities";
+
+/// A row of the `cities` table.
+#[derive(Debug, Clone)]
+pub struct City {
+    pub geoname_id: i64,
+    pub name: String,
+    pub ascii_name: String,
+    pub country_code: String,
+    pub country: String,
+    pub admin1_code: Option<String>,
+    pub population: i64,
+    pub elevation: Option<i64>,
+    pub timezone: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl City {
+    /// Read a city from a row of `SELECT_CITIES`.
+    pub fn from_row(row: &Row) -> rusqlite::Result<City> {
+        Ok(City {
+            geoname_id: row.get(0)?,
+            name: row.get(1)?,
+            ascii_name: row.get(2)?,
+            country_code: row.get(3)?,
+            country: row.get(4)?,
+            admin1_code: row.get(5)?,
+            population: row.get(6)?,
+            elevation: row.get(7)?,
+            timezone: row.get(8)?,
+            latitude: row.get(9)?,
+            longitude: row.get(10)?,
+        })
+    }
+}
+
+/// Inclusive bounds on the population of a city. A missing bound is not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PopulationRange {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl PopulationRange {
+    /// Returns true if the population is within the bounds.
+    pub fn contains(&self, population: i64) -> bool {
+        self.min.is_none_or(|min| population >= min) && self.max.is_none_or(|max| population <= max)
+    }
+}
+
+/// Write the cities to a new SQLite database at the given path, and return the number of rows.
+///
+/// The database is built in a temporary file next to the target and renamed when complete,
+/// so a running server never sees a half written database.
+pub fn write_database<I>(path: &str, cities: I) -> Result<usize, Box<dyn Error>>
+where
+    I: IntoIterator<Item = City>,
+{
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let tmp_path = format!("{}.tmp", path);
+    if Path::new(&tmp_path).exists() {
+        fs::remove_file(&tmp_path)?;
+    }
+
+    let mut connection = Connection::open(&tmp_path)?;
+    connection.execute_batch(SCHEMA)?;
+
+    let transaction = connection.transaction()?;
+    let mut count = 0;
+    {
+        let mut statement = transaction
+            .prepare("INSERT INTO cities VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)")?;
+        for city in cities {
+            statement.execute(params![
+                city.geoname_id,
+                city.name,
+                city.ascii_name,
+                city.country_code,
+                city.country,
+                city.admin1_code,
+                city.population,
+                city.elevation,
+                city.timezone,
+                city.latitude,
+                city.longitude,
+            ])?;
+            count += 1;
+        }
+    }
+    transaction.commit()?;
+
+    connection.execute_batch(INDEXES)?;
+    connection.execute_batch("ANALYZE")?;
+    connection
+        .close()
+        .map_err(|(_, e)| format!("Failed to close database: {}", e))?;
+
+    fs::rename(&tmp_path, path)?;
+    Ok(count)
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use rusqlite::{Connection, OpenFlags};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::dataset::DEFAULT_DATABASE_PATH;
+
+/// Configuration of a `ConnectionPool`.
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Path to the SQLite database.
+    pub path: String,
+    /// Number of connections in the pool.
+    pub size: usize,
+    /// How long a query waits for a free connection before giving up.
+    pub acquire_timeout: Duration,
+    /// Number of prepared statements cached per connection.
+    pub statement_cache_capacity: usize,
+    /// Run queries on the calling task instead of a blocking thread.
+    ///
+    /// Blocks the runtime, but queries finish in the order they started, which the deterministic simulation needs.
+    pub inline_queries: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            path: String::from(DEFAULT_DATABASE_PATH),
+            size: 4,
+            acquire_timeout: Duration::from_secs(5),
+            statement_cache_capacity: 16,
+            inline_queries: false,
+        }
+    }
+}
+
+/// Error from running a query on the pool.
+#[derive(Debug)]
+pub enum PoolError {
+    /// No connection was free within the acquire timeout.
+    Timeout,
+    /// The query failed.
+    Query(rusqlite::Error),
+    /// The blocking task running the query panicked.
+    Panicked,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Timeout => write!(f, "Timed out waiting for a database connection"),
+            PoolError::Query(e) => write!(f, "Query failed: {}", e),
+            PoolError::Panicked => write!(f, "Query task panicked"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// Snapshot of the pool usage.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    /// Number of connections in the pool.
+    pub size: usize,
+    /// Connections currently running a query.
+    pub in_use: usize,
+    /// Queries waiting for a free connection.
+    pub waiting: usize,
+}
+
+impl PoolStats {
+    /// Returns true if every connection is in use.
+    pub fn is_saturated(&self) -> bool {
+        self.in_use >= self.size
+    }
+}
+
+/// Result of a query run on the pool, with the time spent waiting for a connection.
+pub struct PooledResult<T> {
+    pub value: T,
+    pub wait: Duration,
+}
+
+/// Bounded pool of read-only SQLite connections.
+///
+/// Queries run on the blocking thread pool of tokio, so they do not stall the async runtime.
+/// Each connection keeps its own cache of prepared statements, used through `Connection::prepare_cached`.
+pub struct ConnectionPool {
+    config: PoolConfig,
+    /// Free connections, with the generation of the pool they were opened in.
+    connections: Arc<Mutex<Vec<(u64, Connection)>>>,
+    /// One permit per free connection. A permit is only released once its connection is back in `connections`.
+    permits: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    /// Incremented by `reopen`. Connections opened in an older generation are reopened before their next query.
+    generation: AtomicU64,
+}
+
+impl ConnectionPool {
+    /// Open all connections of the pool.
+    pub fn open(config: PoolConfig) -> Result<Self, rusqlite::Error> {
+        let mut connections = Vec::with_capacity(config.size);
+        for _ in 0..config.size {
+            connections.push((0, open_connection(&config)?));
+        }
+
+        Ok(ConnectionPool {
+            permits: Arc::new(Semaphore::new(config.size)),
+            connections: Arc::new(Mutex::new(connections)),
+            waiting: AtomicUsize::new(0),
+            generation: AtomicU64::new(0),
+            config,
+        })
+    }
+
+    /// Path to the database the connections are opened on.
+    pub fn path(&self) -> &str {
+        &self.config.path
+    }
+
+    /// Reopen every connection of the pool before its next query.
+    ///
+    /// A database rebuilt by renaming a new file over the old one is only seen by connections opened after the rename.
+    /// Queries already running finish on their current connection.
+    pub fn reopen(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Get the current usage of the pool.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            size: self.config.size,
+            in_use: self.config.size - self.permits.available_permits(),
+            waiting: self.waiting.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Run a query on a free connection.
+    ///
+    /// Waits at most the acquire timeout for a connection, then runs the query on a blocking thread, or inline if configured.
+    pub async fn run<T, F>(&self, query: F) -> Result<PooledResult<T>, PoolError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let start = Instant::now();
+
+        let permit = {
+            let _waiting = WaitingGuard::new(&self.waiting);
+            tokio::time::timeout(
+                self.config.acquire_timeout,
+                self.permits.clone().acquire_owned(),
+            )
+            .await
+        };
+        let permit = match permit {
+            Ok(Ok(permit)) => permit,
+            _ => return Err(PoolError::Timeout),
+        };
+        let wait = start.elapsed();
+
+        // A permit guarantees a free connection
+        let (opened_in, connection) = self
+            .connections
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("Connection pool has a permit but no connection");
+        let mut connection = PooledConnection {
+            connections: self.connections.clone(),
+            generation: opened_in,
+            connection: Some(connection),
+            _permit: permit,
+        };
+
+        // Replace a connection opened before the last reopen.
+        // If that fails, the old connection goes back to the pool.
+        let generation = self.generation.load(Ordering::SeqCst);
+        if connection.generation != generation {
+            connection.connection = Some(open_connection(&self.config).map_err(PoolError::Query)?);
+            connection.generation = generation;
+        }
+
+        // The connection goes back to the pool when the guard is dropped, even if this future is dropped while the query runs
+        let result = if self.config.inline_queries {
+            Ok(query(connection.connection()))
+        } else {
+            tokio::task::spawn_blocking(move || query(connection.connection())).await
+        };
+
+        match result {
+            Ok(result) => result
+                .map(|value| PooledResult { value, wait })
+                .map_err(PoolError::Query),
+            Err(_) => Err(PoolError::Panicked),
+        }
+    }
+}
+
+/// Counts a query in `waiting` until it gets a permit, gives up or is dropped.
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::SeqCst);
+        WaitingGuard(waiting)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A connection taken from the pool, with its permit.
+///
+/// Dropping it puts the connection back before releasing the permit, so a permit always has a free connection.
+struct PooledConnection {
+    connections: Arc<Mutex<Vec<(u64, Connection)>>>,
+    /// Generation of the pool the connection was opened in.
+    generation: u64,
+    /// Only taken when the guard is dropped.
+    connection: Option<Connection>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    fn connection(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            // Also dropped while unwinding from a panicked query, so a poisoned lock must not panic again
+            self.connections
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((self.generation, connection));
+        }
+    }
+}
+
+/// Open a read-only connection with the configured statement cache.
+fn open_connection(config: &PoolConfig) -> Result<Connection, rusqlite::Error> {
+    let connection = Connection::open_with_flags(
+        &config.path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    connection.set_prepared_statement_cache_capacity(config.statement_cache_capacity);
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// A database with a single row, in the temporary directory and unique to the test.
+    fn database(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("db_pool_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE numbers (n INTEGER); INSERT INTO numbers VALUES (7);")
+            .unwrap();
+        path
+    }
+
+    fn open_pool(path: &Path, size: usize) -> ConnectionPool {
+        ConnectionPool::open(PoolConfig {
+            path: path.to_string_lossy().into_owned(),
+            size,
+            ..PoolConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Read the row after sleeping on the blocking thread.
+    fn slow_query(delay: Duration) -> impl FnOnce(&Connection) -> rusqlite::Result<i64> {
+        move |connection| {
+            std::thread::sleep(delay);
+            connection.query_row("SELECT n FROM numbers", [], |row| row.get(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn query_dropped_while_running_returns_its_connection() {
+        let path = database("dropped_running");
+        let pool = open_pool(&path, 2);
+
+        let dropped = tokio::time::timeout(
+            Duration::from_millis(20),
+            pool.run(slow_query(Duration::from_millis(200))),
+        )
+        .await;
+        assert!(dropped.is_err());
+
+        // One more query than connections, each outlasting the dropped query, so one of them needs its connection
+        let (a, b, c) = tokio::join!(
+            pool.run(slow_query(Duration::from_millis(300))),
+            pool.run(slow_query(Duration::from_millis(300))),
+            pool.run(slow_query(Duration::from_millis(300))),
+        );
+        for result in [a, b, c] {
+            assert_eq!(result.unwrap().value, 7);
+        }
+        assert_eq!(pool.stats().in_use, 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn query_dropped_while_waiting_is_not_counted() {
+        let path = database("dropped_waiting");
+        let pool = open_pool(&path, 1);
+
+        let (slow, waiting) = tokio::join!(
+            pool.run(slow_query(Duration::from_millis(100))),
+            tokio::time::timeout(
+                Duration::from_millis(20),
+                pool.run(slow_query(Duration::ZERO))
+            ),
+        );
+        assert_eq!(slow.unwrap().value, 7);
+        assert!(waiting.is_err());
+
+        let stats = pool.stats();
+        assert_eq!(stats.waiting, 0);
+        assert_eq!(stats.in_use, 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn panicked_query_returns_its_connection() {
+        let path = database("panicked");
+        let pool = open_pool(&path, 1);
+
+        let panicked = pool
+            .run(|_: &Connection| -> rusqlite::Result<i64> { panic!("query panicked") })
+            .await;
+        assert!(matches!(panicked, Err(PoolError::Panicked)));
+
+        for _ in 0..2 {
+            assert_eq!(pool.run(slow_query(Duration::ZERO)).await.unwrap().value, 7);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
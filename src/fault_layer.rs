@@ -0,0 +1,119 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::{Request, Response};
+use tonic::Status;
+use tower::{BoxError, Layer, Service};
+
+use crate::faults::{Fault, FaultModel};
+use crate::latency_layer::{header_value, REQUEST_KEY_HEADER};
+
+/// Tower layer injecting network faults on the channel of a client in `from` zone, connected to a server in `to` zone.
+///
+/// Dropped and partitioned requests fail with `DEADLINE_EXCEEDED` after the drop timeout,
+/// reset connections fail after the server has handled the request, and unavailable links fail with `UNAVAILABLE` at once.
+#[derive(Clone)]
+pub struct FaultLayer {
+    model: Arc<FaultModel>,
+    from: u32,
+    to: u32,
+    counter: Arc<AtomicU64>,
+}
+
+impl FaultLayer {
+    pub fn new(model: Arc<FaultModel>, from: u32, to: u32) -> Self {
+        FaultLayer {
+            model,
+            from,
+            to,
+            counter: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl<S> Layer<S> for FaultLayer {
+    type Service = FaultService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FaultService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by `FaultLayer`.
+#[derive(Clone)]
+pub struct FaultService<S> {
+    inner: S,
+    layer: FaultLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for FaultService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // Use the service that was polled ready, and leave a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let (from, to) = (self.layer.from, self.layer.to);
+        let key = header_value(request.headers(), REQUEST_KEY_HEADER)
+            .unwrap_or_else(|| self.layer.counter.fetch_add(1, Ordering::Relaxed));
+        let fault = self.layer.model.sample(from, to, key);
+        let drop_timeout = self.layer.model.drop_timeout();
+
+        Box::pin(async move {
+            match fault {
+                None => inner.call(request).await.map_err(Into::into),
+                Some(Fault::Drop) => {
+                    tokio::time::sleep(drop_timeout).await;
+                    Err(Status::deadline_exceeded(format!(
+                        "Request from zone {} to zone {} was dropped",
+                        from, to
+                    ))
+                    .into())
+                }
+                Some(Fault::Partitioned) => {
+                    tokio::time::sleep(drop_timeout).await;
+                    Err(Status::deadline_exceeded(format!(
+                        "Zone {} cannot reach zone {} during a network partition",
+                        from, to
+                    ))
+                    .into())
+                }
+                Some(Fault::Unavailable) => Err(Status::unavailable(format!(
+                    "Zone {} is unavailable from zone {}",
+                    to, from
+                ))
+                .into()),
+                Some(Fault::Reset) => {
+                    // The server handles the request, but the response is lost
+                    inner.call(request).await.map_err(Into::into)?;
+                    Err(io::Error::new(
+                        io::ErrorKind::ConnectionReset,
+                        format!("Connection from zone {} to zone {} was reset", from, to),
+                    )
+                    .into())
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,338 @@
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use tokio::time::Instant;
+
+/// Probabilities of the faults on a network link, each between 0 and 1.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FaultRates {
+    /// The request is lost, and the client gives up after the drop timeout.
+    pub drop: f64,
+    /// The request reaches the server, but the connection is reset before the response arrives.
+    pub reset: f64,
+    /// The request is rejected with an `UNAVAILABLE` error without reaching the server.
+    pub unavailable: f64,
+}
+
+impl FaultRates {
+    /// Check that every probability is valid and that they add up to at most 1.
+    fn validate(&self) -> Result<(), String> {
+        let rates = [self.drop, self.reset, self.unavailable];
+        let valid =
+            rates.iter().all(|rate| (0.0..=1.0).contains(rate)) && rates.iter().sum::<f64>() <= 1.0;
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid fault probabilities: {:?}", self))
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.drop == 0.0 && self.reset == 0.0 && self.unavailable == 0.0
+    }
+}
+
+/// Faults between two zones.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FaultLinkEntry {
+    pub from: u32,
+    pub to: u32,
+    /// Also use the entry from `to` to `from`, unless that direction has its own entry.
+    #[serde(default)]
+    pub bidirectional: bool,
+    #[serde(flatten)]
+    pub rates: FaultRates,
+}
+
+/// Scheduled network partition, where `from` zone cannot reach `to` zone between `start_s` and `end_s`.
+///
+/// Times are in seconds since the client started.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Partition {
+    pub from: u32,
+    pub to: u32,
+    /// Also cut the direction from `to` to `from`.
+    #[serde(default)]
+    pub bidirectional: bool,
+    pub start_s: f64,
+    pub end_s: f64,
+}
+
+impl Partition {
+    fn cuts(&self, from: u32, to: u32, elapsed: Duration) -> bool {
+        let matches = (self.from == from && self.to == to)
+            || (self.bidirectional && self.from == to && self.to == from);
+        let elapsed = elapsed.as_secs_f64();
+        matches && self.start_s <= elapsed && elapsed < self.end_s
+    }
+}
+
+/// Injected network faults, given as the `[faults]` section of the topology file.
+///
+/// Zone pairs without an entry in `links` use the `default` rates, which are all 0 unless set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FaultConfig {
+    /// Seed for the faults. Runs with the same seed and requests get the same faults.
+    pub seed: Option<u64>,
+    pub default: FaultRates,
+    pub links: Vec<FaultLinkEntry>,
+    pub partitions: Vec<Partition>,
+    /// How long a dropped or partitioned request waits before failing, in milliseconds.
+    pub drop_timeout_ms: u64,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        FaultConfig {
+            seed: None,
+            default: FaultRates::default(),
+            links: Vec::new(),
+            partitions: Vec::new(),
+            drop_timeout_ms: 1000,
+        }
+    }
+}
+
+impl FaultConfig {
+    /// Check that every probability is valid, and that links and partitions are between known zones.
+    pub fn validate(&self, zone_exists: impl Fn(u32) -> bool) -> Result<(), String> {
+        self.default.validate()?;
+
+        for entry in &self.links {
+            if !zone_exists(entry.from) || !zone_exists(entry.to) {
+                return Err(format!(
+                    "Fault link from zone {} to zone {} uses an unknown zone",
+                    entry.from, entry.to
+                ));
+            }
+            entry.rates.validate()?;
+        }
+
+        for partition in &self.partitions {
+            if !zone_exists(partition.from) || !zone_exists(partition.to) {
+                return Err(format!(
+                    "Partition from zone {} to zone {} uses an unknown zone",
+                    partition.from, partition.to
+                ));
+            }
+            if !(0.0 <= partition.start_s && partition.start_s < partition.end_s) {
+                return Err(format!(
+                    "Partition from zone {} to zone {} must end after it starts",
+                    partition.from, partition.to
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if any fault or partition is configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.default.is_zero()
+            || self.links.iter().any(|entry| !entry.rates.is_zero())
+            || !self.partitions.is_empty()
+    }
+}
+
+/// A fault injected on a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Drop,
+    Reset,
+    Unavailable,
+    /// The zones are partitioned at the time of the request.
+    Partitioned,
+}
+
+/// Decides which requests fail, and how.
+pub struct FaultModel {
+    config: FaultConfig,
+    seed: u64,
+    /// Start of the run, which partition times are relative to.
+    start: Instant,
+}
+
+impl FaultModel {
+    /// Create a model from a validated configuration, starting the partition clock.
+    ///
+    /// Without a seed in the configuration a random seed is used.
+    pub fn new(config: FaultConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        FaultModel {
+            config,
+            seed,
+            start: Instant::now(),
+        }
+    }
+
+    /// Seed used for the faults.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns true if any fault or partition is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    /// How long a dropped or partitioned request waits before failing.
+    pub fn drop_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.drop_timeout_ms)
+    }
+
+    /// Get the fault rates used from one zone to another.
+    pub fn rates(&self, from: u32, to: u32) -> &FaultRates {
+        let exact = self
+            .config
+            .links
+            .iter()
+            .find(|entry| entry.from == from && entry.to == to);
+        let reverse = || {
+            self.config
+                .links
+                .iter()
+                .find(|entry| entry.bidirectional && entry.from == to && entry.to == from)
+        };
+
+        match exact.or_else(reverse) {
+            Some(entry) => &entry.rates,
+            None => &self.config.default,
+        }
+    }
+
+    /// Returns true if `from` zone cannot reach `to` zone right now.
+    pub fn is_partitioned(&self, from: u32, to: u32) -> bool {
+        let elapsed = self.start.elapsed();
+        self.config
+            .partitions
+            .iter()
+            .any(|partition| partition.cuts(from, to, elapsed))
+    }
+
+    /// Decide the fault of a request from one zone to another, if any.
+    ///
+    /// Apart from partitions, the fault only depends on the seed, the zones and the request key.
+    pub fn sample(&self, from: u32, to: u32, request_key: u64) -> Option<Fault> {
+        if self.is_partitioned(from, to) {
+            return Some(Fault::Partitioned);
+        }
+
+        let rates = self.rates(from, to);
+        if rates.is_zero() {
+            return None;
+        }
+
+        let stream = (u64::from(from) << 32) | u64::from(to);
+        let mut rng = StdRng::seed_from_u64(
+            self.seed ^ request_key.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ stream,
+        );
+
+        let draw: f64 = rng.gen();
+        if draw < rates.drop {
+            Some(Fault::Drop)
+        } else if draw < rates.drop + rates.reset {
+            Some(Fault::Reset)
+        } else if draw < rates.drop + rates.reset + rates.unavailable {
+            Some(Fault::Unavailable)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> FaultConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn count(model: &FaultModel, from: u32, to: u32, fault: Fault) -> usize {
+        (0..10_000)
+            .filter(|key| model.sample(from, to, *key) == Some(fault))
+            .count()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_faults() {
+        let toml = "seed = 11\ndefault = { drop = 0.2, reset = 0.2, unavailable = 0.2 }";
+        let a = FaultModel::new(config(toml));
+        let b = FaultModel::new(config(toml));
+
+        for key in 0..1000 {
+            assert_eq!(a.sample(1, 2, key), b.sample(1, 2, key));
+        }
+    }
+
+    #[test]
+    fn faults_follow_the_link_rates() {
+        let model = FaultModel::new(config(
+            r#"
+            seed = 5
+            default = { unavailable = 0.1 }
+
+            [[links]]
+            from = 1
+            to = 2
+            bidirectional = true
+            drop = 0.5
+            "#,
+        ));
+
+        // Roughly the configured share of 10000 requests
+        assert!((4500..5500).contains(&count(&model, 1, 2, Fault::Drop)));
+        assert!((4500..5500).contains(&count(&model, 2, 1, Fault::Drop)));
+        assert_eq!(count(&model, 1, 2, Fault::Unavailable), 0);
+        assert!((800..1200).contains(&count(&model, 1, 3, Fault::Unavailable)));
+        assert_eq!(count(&model, 1, 3, Fault::Drop), 0);
+    }
+
+    #[test]
+    fn no_faults_without_rates() {
+        let model = FaultModel::new(FaultConfig::default());
+
+        assert!(!model.is_enabled());
+        assert!((0..1000).all(|key| model.sample(1, 2, key).is_none()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn partitions_cut_links_between_their_start_and_end() {
+        let model = FaultModel::new(config(
+            "partitions = [{ from = 1, to = 2, start_s = 10, end_s = 20 }]",
+        ));
+
+        assert!(!model.is_partitioned(1, 2));
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(model.sample(1, 2, 0), Some(Fault::Partitioned));
+        // Only the given direction is cut
+        assert_eq!(model.sample(2, 1, 0), None);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(!model.is_partitioned(1, 2));
+    }
+
+    #[test]
+    fn rejects_invalid_configurations() {
+        let any_zone = |_| true;
+
+        assert!(config("default = { drop = 1.5 }")
+            .validate(any_zone)
+            .is_err());
+        assert!(config("default = { drop = 0.6, reset = 0.6 }")
+            .validate(any_zone)
+            .is_err());
+        assert!(
+            config("partitions = [{ from = 1, to = 2, start_s = 5, end_s = 5 }]")
+                .validate(any_zone)
+                .is_err()
+        );
+        assert!(config("links = [{ from = 1, to = 7, drop = 0.1 }]")
+            .validate(|zone| zone < 5)
+            .is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use rs_distributed_stats::dataset::{write_database, DEFAULT_DATABASE_PATH};
+use rs_distributed_stats::synthetic::{PopulationDistribution, SyntheticConfig, SyntheticDataset};
+use rs_distributed_stats::topology::{Topology, DEFAULT_TOPOLOGY_PATH};
+use rs_distributed_stats::workload::{
+    write_request_file, RequestFormat, RequestGenerator, WorkloadConfig,
+};
+
+/// Options of the generator given as command-line flags.
+struct GenerateOptions {
+    dataset: SyntheticConfig,
+    /// Path of the database to write.
+    output: String,
+    /// Directory to write the request files to. No request files are written if not set.
+    requests_dir: Option<String>,
+    /// Number of request files, one per client.
+    clients: usize,
+    /// Number of requests in each file.
+    requests: usize,
+    topology_path: String,
+}
+
+/// Parse the optional generator flags.
+///
+/// `--seed <seed>`, `--countries <count>`, `--cities <count | min-max>` and `--population <distribution>` configure the dataset.
+/// The population distribution is `uniform:<min>:<max>`, `log_normal:<mu>:<sigma>` or `pareto:<scale>:<shape>`.
+/// `--output <path>` writes the database to the given path instead of `db/city_database.db`.
+/// `--requests <dir>` also writes `--clients <count>` request files with `--requests-per-client <count>` lines,
+/// sent to the zones of the topology from `--topology <path>`.
+fn parse_options(flags: &[String]) -> Result<GenerateOptions, Box<dyn Error>> {
+    let mut options = GenerateOptions {
+        dataset: SyntheticConfig::default(),
+        output: String::from(DEFAULT_DATABASE_PATH),
+        requests_dir: None,
+        clients: 5,
+        requests: 1000,
+        topology_path: String::from(DEFAULT_TOPOLOGY_PATH),
+    };
+
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", flag))?;
+
+        match flag.as_str() {
+            "--seed" => options.dataset.seed = value.parse::<u64>()?,
+            "--countries" => options.dataset.countries = value.parse::<usize>()?,
+            "--cities" => {
+                options.dataset.cities_per_country = match value.split_once('-') {
+                    Some((min, max)) => (min.parse::<usize>()?, max.parse::<usize>()?),
+                    None => {
+                        let count = value.parse::<usize>()?;
+                        (count, count)
+                    }
+                }
+            }
+            "--population" => {
+                options.dataset.population = value.parse::<PopulationDistribution>()?
+            }
+            "--output" => options.output = value.clone(),
+            "--requests" => options.requests_dir = Some(value.clone()),
+            "--clients" => options.clients = value.parse::<usize>()?,
+            "--requests-per-client" => options.requests = value.parse::<usize>()?,
+            "--topology" => options.topology_path = value.clone(),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    Ok(options)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--help") {
+        eprintln!(
+            "Usage: {} [--seed <seed>] [--countries <count>] [--cities <count | min-max>] [--population <uniform:<min>:<max> | log_normal:<mu>:<sigma> | pareto:<scale>:<shape>>] [--output <path>] [--requests <dir>] [--clients <count>] [--requests-per-client <count>] [--topology <path>]",
+            args[0]
+        );
+        return Ok(());
+    }
+    let options = parse_options(&args[1..])?;
+
+    let dataset = SyntheticDataset::generate(&options.dataset)?;
+    println!(
+        "[INFO] Generated {} cities in {} countries with seed {}",
+        dataset.cities.len(),
+        dataset.countries.len(),
+        options.dataset.seed
+    );
+
+    let countries = dataset.countries.clone();
+    let populations = dataset.sorted_populations();
+
+    let written = write_database(&options.output, dataset.cities)?;
+    println!("[INFO] Wrote {} cities to {}", written, options.output);
+
+    let Some(requests_dir) = &options.requests_dir else {
+        return Ok(());
+    };
+
+    // Match the request parameters to the generated data, so the requests have non-trivial results
+    let topology = Topology::load(&options.topology_path)?;
+    let quantile = |q: f64| {
+        let index = (populations.len().saturating_sub(1) as f64 * q) as usize;
+        populations.get(index).copied().unwrap_or(0).max(0) as u64
+    };
+
+    let mut workload = WorkloadConfig::new(
+        countries,
+        topology.zones.iter().map(|zone| zone.id).collect(),
+    );
+    workload.population_range = quantile(0.1)..=quantile(0.99);
+    workload.city_count_range = 1..=options.dataset.cities_per_country.1.max(1) as u32;
+    workload.validate()?;
+
+    fs::create_dir_all(requests_dir)?;
+    for client in 1..=options.clients {
+        // Each client gets its own stream of requests
+        let seed = options.dataset.seed.wrapping_add(client as u64);
+        let mut generator = RequestGenerator::new(workload.clone(), seed);
+
+        let path = format!("{}/client_{}.txt", requests_dir, client);
+        write_request_file(&path, &mut generator, options.requests, RequestFormat::Text)?;
+        println!("[INFO] Wrote {} requests to {}", options.requests, path);
+    }
+
+    Ok(())
+}
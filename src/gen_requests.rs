@@ -0,0 +1,155 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use rs_distributed_stats::topology::{Topology, DEFAULT_TOPOLOGY_PATH};
+use rs_distributed_stats::workload::{
+    write_request_file, RequestFormat, RequestGenerator, WorkloadConfig, DEFAULT_COUNTRIES,
+};
+
+/// Options of the generator given as command-line flags.
+struct GenerateOptions {
+    seed: u64,
+    /// Number of request files, one per client.
+    clients: Option<usize>,
+    /// Number of requests in each file.
+    requests: usize,
+    function_weights: [f64; 4],
+    country_skew: f64,
+    /// Share of the requests sent to the zone of the client. Not set spreads the requests evenly.
+    home_affinity: Option<f64>,
+    population_range: RangeInclusive<u64>,
+    city_count_range: RangeInclusive<u32>,
+    countries: Vec<String>,
+    format: RequestFormat,
+    topology_path: String,
+}
+
+/// Parse a range given as `<min>-<max>`.
+fn parse_range<T: FromStr + PartialOrd>(value: &str) -> Result<RangeInclusive<T>, String> {
+    let invalid = || format!("Invalid range: {}", value);
+    let (min, max) = value.split_once('-').ok_or_else(invalid)?;
+    let min = min.parse::<T>().map_err(|_| invalid())?;
+    let max = max.parse::<T>().map_err(|_| invalid())?;
+    Ok(min..=max)
+}
+
+/// Parse the function weights given as `<population>:<cities>:<countries>:<countries max>`.
+fn parse_mix(value: &str) -> Result<[f64; 4], String> {
+    let weights = value
+        .split(':')
+        .map(|weight| weight.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| format!("Invalid function mix: {}", value))?;
+
+    weights
+        .try_into()
+        .map_err(|_| format!("The function mix needs 4 weights: {}", value))
+}
+
+/// Parse the optional generator flags.
+///
+/// `--seed <seed>`, `--clients <count>` and `--requests <count>` set the seed, the number of files and the lines per file.
+/// The clients default to one per zone in the topology from `--topology <path>`.
+/// `--mix <population>:<cities>:<countries>:<countries max>` sets the relative weights of the functions.
+/// `--zipf <exponent>` skews the country popularity, where the first country in `--countries <a,b,...>` is the most popular.
+/// `--home-affinity <share>` sends the given share of the requests of client N to the Nth zone of the topology.
+/// `--population-range <min-max>` and `--city-count-range <min-max>` set the parameter ranges.
+/// `--format <text | jsonl>` writes the text format (default) or the version 2 JSON Lines format.
+fn parse_options(flags: &[String]) -> Result<GenerateOptions, Box<dyn Error>> {
+    let mut options = GenerateOptions {
+        seed: 42,
+        clients: None,
+        requests: 1000,
+        function_weights: [1.0; 4],
+        country_skew: 0.0,
+        home_affinity: None,
+        population_range: 100_000..=9_999_999,
+        city_count_range: 1..=10,
+        countries: DEFAULT_COUNTRIES.iter().map(|c| c.to_string()).collect(),
+        format: RequestFormat::Text,
+        topology_path: String::from(DEFAULT_TOPOLOGY_PATH),
+    };
+
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", flag))?;
+
+        match flag.as_str() {
+            "--seed" => options.seed = value.parse::<u64>()?,
+            "--clients" => options.clients = Some(value.parse::<usize>()?),
+            "--requests" => options.requests = value.parse::<usize>()?,
+            "--mix" => options.function_weights = parse_mix(value)?,
+            "--zipf" => options.country_skew = value.parse::<f64>()?,
+            "--home-affinity" => options.home_affinity = Some(value.parse::<f64>()?),
+            "--population-range" => options.population_range = parse_range(value)?,
+            "--city-count-range" => options.city_count_range = parse_range(value)?,
+            "--countries" => {
+                options.countries = value.split(',').map(|c| c.trim().to_string()).collect()
+            }
+            "--format" => options.format = value.parse::<RequestFormat>()?,
+            "--topology" => options.topology_path = value.clone(),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    if options.countries.iter().any(|c| c.contains(' ')) {
+        return Err("Country names in request files can not contain spaces".into());
+    }
+
+    Ok(options)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <Output directory> [--seed <seed>] [--clients <count>] [--requests <count>] [--mix <w1:w2:w3:w4>] [--zipf <exponent>] [--home-affinity <share>] [--population-range <min-max>] [--city-count-range <min-max>] [--countries <a,b,...>] [--format <text | jsonl>] [--topology <path>]",
+            args[0]
+        );
+        return Ok(());
+    }
+    let output_dir = &args[1];
+    let options = parse_options(&args[2..])?;
+
+    let topology = Topology::load(&options.topology_path)?;
+    let zones: Vec<u32> = topology.zones.iter().map(|zone| zone.id).collect();
+    if zones.is_empty() {
+        return Err(format!("The topology {} has no zones", options.topology_path).into());
+    }
+    let clients = options.clients.unwrap_or(zones.len());
+
+    fs::create_dir_all(output_dir)?;
+    for client in 1..=clients {
+        let mut workload = WorkloadConfig::new(options.countries.clone(), zones.clone());
+        workload.function_weights = options.function_weights;
+        workload.country_skew = options.country_skew;
+        workload.population_range = options.population_range.clone();
+        workload.city_count_range = options.city_count_range.clone();
+        if let Some(affinity) = options.home_affinity {
+            workload.home_zone = Some(zones[(client - 1) % zones.len()]);
+            workload.home_affinity = affinity;
+        }
+        workload.validate()?;
+
+        // Each client gets its own stream of requests
+        let seed = options.seed.wrapping_add(client as u64);
+        let mut generator = RequestGenerator::new(workload, seed);
+
+        let path = format!(
+            "{}/client_{}.{}",
+            output_dir,
+            client,
+            options.format.extension()
+        );
+        write_request_file(&path, &mut generator, options.requests, options.format)?;
+        println!("[INFO] Wrote {} requests to {}", options.requests, path);
+    }
+
+    Ok(())
+}
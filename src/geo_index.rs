@@ -0,0 +1,404 @@
+use rstar::primitives::GeomWithData;
+use rstar::{RTree, AABB};
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+use crate::dataset::PopulationRange;
+
+/// Mean radius of the Earth in kilometres.
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Position and population of a city in the index, enough to filter it without reading the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexedCity {
+    pub geoname_id: i64,
+    pub population: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// A city found by a query, with its great-circle distance in kilometres from the query point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CityDistance {
+    pub city: IndexedCity,
+    pub distance_km: f64,
+}
+
+/// Spatial index of the cities, built from the `cities` table.
+///
+/// Distance queries use an R-tree of the cities on a sphere of the Earth's radius in 3D space.
+/// The straight-line distance between two points on the sphere grows with their great-circle distance,
+/// so the nearest cities in the tree are the nearest on the Earth. Bounding boxes use a second R-tree of
+/// longitude and latitude.
+pub struct GeoIndex {
+    cities: Vec<IndexedCity>,
+    /// Position of each city on the sphere, with its index in `cities`.
+    sphere: RTree<GeomWithData<[f64; 3], usize>>,
+    /// Longitude and latitude of each city, with its index in `cities`.
+    plane: RTree<GeomWithData<[f64; 2], usize>>,
+}
+
+impl GeoIndex {
+    /// Build the index of the given cities.
+    pub fn new(cities: Vec<IndexedCity>) -> Self {
+        let sphere = cities
+            .iter()
+            .enumerate()
+            .map(|(index, city)| GeomWithData::new(to_sphere(city.latitude, city.longitude), index))
+            .collect();
+        let plane = cities
+            .iter()
+            .enumerate()
+            .map(|(index, city)| GeomWithData::new([city.longitude, city.latitude], index))
+            .collect();
+
+        GeoIndex {
+            cities,
+            sphere: RTree::bulk_load(sphere),
+            plane: RTree::bulk_load(plane),
+        }
+    }
+
+    /// Build the index of every city in the database.
+    ///
+    /// Cities without an integer Geoname ID and population or numeric coordinates, as in databases built from the raw CSV, are left out.
+    pub fn load(connection: &Connection) -> rusqlite::Result<Self> {
+        let mut statement = connection
+            .prepare("SELECT [Geoname ID], [Population], [Latitude], [Longitude] FROM cities")?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Value>(0)?,
+                    row.get::<_, Value>(1)?,
+                    row.get::<_, Value>(2)?,
+                    row.get::<_, Value>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let total = rows.len();
+        let cities: Vec<IndexedCity> = rows
+            .into_iter()
+            .filter_map(|(geoname_id, population, latitude, longitude)| {
+                let (Value::Integer(geoname_id), Value::Integer(population)) =
+                    (geoname_id, population)
+                else {
+                    return None;
+                };
+                Some(IndexedCity {
+                    geoname_id,
+                    population,
+                    latitude: as_f64(latitude)?,
+                    longitude: as_f64(longitude)?,
+                })
+            })
+            .collect();
+
+        if cities.len() < total {
+            println!(
+                "[WARN] Left {} cities without a Geoname ID, population or coordinates out of the spatial index",
+                total - cities.len()
+            );
+        }
+        Ok(GeoIndex::new(cities))
+    }
+
+    /// Number of cities in the index.
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    /// Returns true if the index has no cities.
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    /// Find the cities within the radius of a point with a population in the range, nearest first.
+    pub fn within_radius(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+        population: PopulationRange,
+    ) -> Vec<CityDistance> {
+        // Straight-line distance through the sphere of the radius along its surface
+        let angle = (radius_km / EARTH_RADIUS_KM).min(std::f64::consts::PI);
+        let chord = 2.0 * EARTH_RADIUS_KM * (angle / 2.0).sin();
+
+        let center = to_sphere(latitude, longitude);
+        let mut found: Vec<CityDistance> = self
+            .sphere
+            .locate_within_distance(center, chord * chord)
+            .map(|entry| self.cities[entry.data])
+            .filter(|city| population.contains(city.population))
+            .map(|city| CityDistance {
+                city,
+                distance_km: distance_km(latitude, longitude, city.latitude, city.longitude),
+            })
+            .filter(|found| found.distance_km <= radius_km)
+            .collect();
+
+        found.sort_by(|a, b| {
+            a.distance_km
+                .total_cmp(&b.distance_km)
+                .then(a.city.geoname_id.cmp(&b.city.geoname_id))
+        });
+        found
+    }
+
+    /// Find the `k` cities nearest to a point with a population in the range, nearest first.
+    pub fn nearest(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        k: usize,
+        population: PopulationRange,
+    ) -> Vec<CityDistance> {
+        self.sphere
+            .nearest_neighbor_iter(&to_sphere(latitude, longitude))
+            .map(|entry| self.cities[entry.data])
+            .filter(|city| population.contains(city.population))
+            .take(k)
+            .map(|city| CityDistance {
+                city,
+                distance_km: distance_km(latitude, longitude, city.latitude, city.longitude),
+            })
+            .collect()
+    }
+
+    /// Find the cities inside a bounding box with a population in the range, largest first.
+    ///
+    /// A box with a minimum longitude above its maximum longitude crosses the antimeridian.
+    pub fn in_bounding_box(
+        &self,
+        min_latitude: f64,
+        min_longitude: f64,
+        max_latitude: f64,
+        max_longitude: f64,
+        population: PopulationRange,
+    ) -> Vec<IndexedCity> {
+        let boxes = if min_longitude <= max_longitude {
+            vec![(min_longitude, max_longitude)]
+        } else {
+            vec![(min_longitude, 180.0), (-180.0, max_longitude)]
+        };
+
+        let mut found: Vec<IndexedCity> = boxes
+            .into_iter()
+            .flat_map(|(west, east)| {
+                let envelope = AABB::from_corners([west, min_latitude], [east, max_latitude]);
+                self.plane
+                    .locate_in_envelope(&envelope)
+                    .map(|entry| entry.data)
+                    .collect::<Vec<usize>>()
+            })
+            .map(|index| self.cities[index])
+            .filter(|city| population.contains(city.population))
+            .collect();
+
+        // Cities on the antimeridian are in both boxes
+        found.sort_by(|a, b| {
+            b.population
+                .cmp(&a.population)
+                .then(a.geoname_id.cmp(&b.geoname_id))
+        });
+        found.dedup_by_key(|city| city.geoname_id);
+        found
+    }
+}
+
+/// Great-circle distance in kilometres between two points, with the haversine formula.
+pub fn distance_km(latitude_a: f64, longitude_a: f64, latitude_b: f64, longitude_b: f64) -> f64 {
+    let (lat_a, lat_b) = (latitude_a.to_radians(), latitude_b.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (longitude_b - longitude_a).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+/// Read a coordinate stored as a real or an integer.
+fn as_f64(value: Value) -> Option<f64> {
+    match value {
+        Value::Real(value) => Some(value),
+        Value::Integer(value) => Some(value as f64),
+        _ => None,
+    }
+}
+
+/// Position of a point on a sphere of the Earth's radius, in kilometres from its center.
+fn to_sphere(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (lat, lon) = (latitude.to_radians(), longitude.to_radians());
+    [
+        EARTH_RADIUS_KM * lat.cos() * lon.cos(),
+        EARTH_RADIUS_KM * lat.cos() * lon.sin(),
+        EARTH_RADIUS_KM * lat.sin(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn city(geoname_id: i64, population: i64, latitude: f64, longitude: f64) -> IndexedCity {
+        IndexedCity {
+            geoname_id,
+            population,
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Cities on a grid over the whole globe, every 10 degrees.
+    fn grid() -> Vec<IndexedCity> {
+        let mut cities = Vec::new();
+        for latitude in (-80..=80).step_by(10) {
+            for longitude in (-180..180).step_by(10) {
+                let id = cities.len() as i64;
+                cities.push(city(id, id * 100, latitude as f64, longitude as f64));
+            }
+        }
+        cities
+    }
+
+    fn ids(found: &[CityDistance]) -> Vec<i64> {
+        found.iter().map(|found| found.city.geoname_id).collect()
+    }
+
+    #[test]
+    fn distances_match_known_values() {
+        // Oslo to Stockholm is about 417 km
+        let oslo_stockholm = distance_km(59.9139, 10.7522, 59.3293, 18.0686);
+        assert!((oslo_stockholm - 417.0).abs() < 5.0);
+
+        let antipodes = distance_km(0.0, 0.0, 0.0, 180.0);
+        assert!((antipodes - std::f64::consts::PI * EARTH_RADIUS_KM).abs() < 1e-6);
+        assert_eq!(distance_km(12.0, 34.0, 12.0, 34.0), 0.0);
+    }
+
+    #[test]
+    fn radius_matches_brute_force() {
+        let cities = grid();
+        let index = GeoIndex::new(cities.clone());
+
+        for (latitude, longitude, radius_km) in [(0.0, 0.0, 2000.0), (60.0, 175.0, 1500.0)] {
+            let found =
+                index.within_radius(latitude, longitude, radius_km, PopulationRange::default());
+
+            let mut expected: Vec<(f64, i64)> = cities
+                .iter()
+                .map(|c| {
+                    (
+                        distance_km(latitude, longitude, c.latitude, c.longitude),
+                        c.geoname_id,
+                    )
+                })
+                .filter(|(distance, _)| *distance <= radius_km)
+                .collect();
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+            assert!(!found.is_empty());
+            assert_eq!(
+                ids(&found),
+                expected.iter().map(|(_, id)| *id).collect::<Vec<i64>>()
+            );
+        }
+    }
+
+    #[test]
+    fn radius_crosses_the_antimeridian() {
+        let index = GeoIndex::new(vec![
+            city(1, 10, 0.0, 179.5),
+            city(2, 10, 0.0, -179.0),
+            city(3, 10, 0.0, 170.0),
+        ]);
+
+        let found = index.within_radius(0.0, 180.0, 150.0, PopulationRange::default());
+
+        assert_eq!(ids(&found), [1, 2]);
+    }
+
+    #[test]
+    fn nearest_matches_brute_force_with_a_population_filter() {
+        let cities = grid();
+        let index = GeoIndex::new(cities.clone());
+        let population = PopulationRange {
+            min: Some(10_000),
+            max: Some(50_000),
+        };
+
+        let found = index.nearest(45.0, -75.0, 5, population);
+
+        let mut expected: Vec<(f64, i64)> = cities
+            .iter()
+            .filter(|c| population.contains(c.population))
+            .map(|c| {
+                (
+                    distance_km(45.0, -75.0, c.latitude, c.longitude),
+                    c.geoname_id,
+                )
+            })
+            .collect();
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(found.len(), 5);
+        for (found, (distance, _)) in found.iter().zip(&expected) {
+            assert!((found.distance_km - distance).abs() < 1e-9);
+            assert!(population.contains(found.city.population));
+        }
+    }
+
+    #[test]
+    fn bounding_box_across_the_antimeridian() {
+        let index = GeoIndex::new(vec![
+            city(1, 300, 10.0, 175.0),
+            city(2, 200, 10.0, -175.0),
+            city(3, 100, 10.0, 0.0),
+            city(4, 400, 10.0, 180.0),
+            city(5, 500, 30.0, 178.0),
+        ]);
+
+        let found = index.in_bounding_box(0.0, 170.0, 20.0, -170.0, PopulationRange::default());
+        let found_ids: Vec<i64> = found.iter().map(|c| c.geoname_id).collect();
+
+        // Largest first, with the city on the antimeridian only once
+        assert_eq!(found_ids, [4, 1, 2]);
+    }
+
+    #[test]
+    fn bounding_box_filters_on_population() {
+        let index = GeoIndex::new(grid());
+        let population = PopulationRange {
+            min: None,
+            max: Some(30_000),
+        };
+
+        let found = index.in_bounding_box(-10.0, -10.0, 10.0, 10.0, population);
+
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|c| c.population <= 30_000
+            && (-10.0..=10.0).contains(&c.latitude)
+            && (-10.0..=10.0).contains(&c.longitude)));
+        assert!(found
+            .windows(2)
+            .all(|pair| pair[0].population >= pair[1].population));
+    }
+
+    #[test]
+    fn loads_cities_and_skips_rows_without_an_integer_id_or_population() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE cities ([Geoname ID], [Population], [Latitude], [Longitude]);
+                 INSERT INTO cities VALUES (1, 1000, 59.9, 10.7), (2, NULL, 60.0, 11.0),
+                     (3, 'unknown', 61.0, 12.0), (4, 2000, 62, 13),
+                     ('5a', 3000, 62.0, 13.0), (NULL, 4000, 62.0, 13.0);",
+            )
+            .unwrap();
+
+        let index = GeoIndex::load(&connection).unwrap();
+
+        assert_eq!(index.len(), 2);
+        let found = index.nearest(62.0, 13.0, 10, PopulationRange::default());
+        assert_eq!(ids(&found), [4, 1]);
+    }
+}
@@ -0,0 +1,421 @@
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, LogNormal, Normal, Pareto, Uniform};
+use serde::Deserialize;
+
+/// Random delay added on top of the base delay of a link, in milliseconds.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum Jitter {
+    /// No random delay.
+    #[default]
+    None,
+    /// Always the same extra delay.
+    Constant { ms: f64 },
+    /// Uniform between `min_ms` and `max_ms`.
+    Uniform { min_ms: f64, max_ms: f64 },
+    /// Normal with the given mean and standard deviation.
+    Normal { mean_ms: f64, std_dev_ms: f64 },
+    /// Log-normal, where `mu` and `sigma` are the mean and standard deviation of the natural log of the delay.
+    LogNormal { mu: f64, sigma: f64 },
+    /// Pareto with the given scale (minimum) and shape, giving a heavy tail.
+    Pareto { scale_ms: f64, shape: f64 },
+}
+
+impl Jitter {
+    /// Check that the parameters give a valid distribution.
+    fn validate(&self) -> Result<(), String> {
+        let valid = match *self {
+            Jitter::None => true,
+            Jitter::Constant { ms } => ms.is_finite(),
+            Jitter::Uniform { min_ms, max_ms } => {
+                min_ms.is_finite() && max_ms.is_finite() && min_ms <= max_ms
+            }
+            Jitter::Normal {
+                mean_ms,
+                std_dev_ms,
+            } => mean_ms.is_finite() && Normal::new(0.0, std_dev_ms).is_ok(),
+            Jitter::LogNormal { mu, sigma } => LogNormal::new(mu, sigma).is_ok(),
+            Jitter::Pareto { scale_ms, shape } => Pareto::new(scale_ms, shape).is_ok(),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid jitter distribution: {:?}", self))
+        }
+    }
+
+    /// Draw a jitter sample in milliseconds.
+    fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        match *self {
+            Jitter::None => 0.0,
+            Jitter::Constant { ms } => ms,
+            Jitter::Uniform { min_ms, max_ms } => {
+                Uniform::new_inclusive(min_ms, max_ms).sample(rng)
+            }
+            Jitter::Normal {
+                mean_ms,
+                std_dev_ms,
+            } => Normal::new(mean_ms, std_dev_ms).unwrap().sample(rng),
+            Jitter::LogNormal { mu, sigma } => LogNormal::new(mu, sigma).unwrap().sample(rng),
+            Jitter::Pareto { scale_ms, shape } => Pareto::new(scale_ms, shape).unwrap().sample(rng),
+        }
+    }
+}
+
+/// Delay of a network link: a base delay plus jitter.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Link {
+    pub base_ms: f64,
+    #[serde(default)]
+    pub jitter: Jitter,
+}
+
+impl Link {
+    fn constant(base_ms: f64) -> Self {
+        Link {
+            base_ms,
+            jitter: Jitter::None,
+        }
+    }
+
+    /// Check that the base delay is not negative and the jitter distribution is valid.
+    fn validate(&self) -> Result<(), String> {
+        if !(self.base_ms.is_finite() && self.base_ms >= 0.0) {
+            return Err(format!("Invalid base delay: {}", self.base_ms));
+        }
+        self.jitter.validate()
+    }
+}
+
+/// Link between two zones in the latency matrix.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LinkEntry {
+    pub from: u32,
+    pub to: u32,
+    /// Also use the link from `to` to `from`, unless that direction has its own entry.
+    #[serde(default)]
+    pub bidirectional: bool,
+    #[serde(flatten)]
+    pub link: Link,
+}
+
+/// Latency between zones, given as the `[latency]` section of the topology file.
+///
+/// Links are directional, so the matrix can be asymmetric. Zone pairs without an entry
+/// use `same_zone` or `cross_zone`, which default to 40 ms and 85 ms without jitter.
+/// Delays are one-way: a call is delayed by the link to the server and by the link back,
+/// so the default round trips take 80 ms and 170 ms.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LatencyConfig {
+    /// Seed for the jitter. Runs with the same seed and requests get the same delays.
+    pub seed: Option<u64>,
+    pub same_zone: Link,
+    pub cross_zone: Link,
+    pub links: Vec<LinkEntry>,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            seed: None,
+            same_zone: Link::constant(40.0),
+            cross_zone: Link::constant(85.0),
+            links: Vec::new(),
+        }
+    }
+}
+
+impl LatencyConfig {
+    /// Check that every link has a valid base delay and jitter distribution, and is between known zones.
+    pub fn validate(&self, zone_exists: impl Fn(u32) -> bool) -> Result<(), String> {
+        self.same_zone.validate()?;
+        self.cross_zone.validate()?;
+
+        for entry in &self.links {
+            if !zone_exists(entry.from) || !zone_exists(entry.to) {
+                return Err(format!(
+                    "Latency link from zone {} to zone {} uses an unknown zone",
+                    entry.from, entry.to
+                ));
+            }
+            entry.link.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Samples the simulated network delay between zones.
+pub struct LatencyModel {
+    config: LatencyConfig,
+    seed: u64,
+}
+
+impl LatencyModel {
+    /// Create a model from a validated configuration.
+    ///
+    /// Without a seed in the configuration a random seed is used.
+    pub fn new(config: LatencyConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        LatencyModel { config, seed }
+    }
+
+    /// Seed used for the jitter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Get the link used from one zone to another.
+    pub fn link(&self, from: u32, to: u32) -> &Link {
+        let exact = self
+            .config
+            .links
+            .iter()
+            .find(|entry| entry.from == from && entry.to == to);
+        let reverse = || {
+            self.config
+                .links
+                .iter()
+                .find(|entry| entry.bidirectional && entry.from == to && entry.to == from)
+        };
+
+        match exact.or_else(reverse) {
+            Some(entry) => &entry.link,
+            None if from == to => &self.config.same_zone,
+            None => &self.config.cross_zone,
+        }
+    }
+
+    /// Sample the delay from one zone to another for a request.
+    ///
+    /// The jitter only depends on the seed, the zones and the request key,
+    /// so a request gets the same delay in every run no matter the order requests are sent in.
+    pub fn sample(&self, from: u32, to: u32, request_key: u64) -> Duration {
+        let link = self.link(from, to);
+
+        let stream = (u64::from(from) << 32) | u64::from(to);
+        let mut rng = StdRng::seed_from_u64(
+            self.seed ^ request_key.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ stream,
+        );
+
+        to_delay(link.base_ms + link.jitter.sample(&mut rng))
+    }
+
+    /// Sleep for the delay from one zone to another, and return the delay.
+    pub async fn delay(&self, from: u32, to: u32, request_key: u64) -> Duration {
+        let delay = self.sample(from, to, request_key);
+        tokio::time::sleep(delay).await;
+        delay
+    }
+}
+
+/// Simulated time a server spends on each query, given as the `[service_time]` section of the topology file.
+///
+/// Disabled unless set. Results answered from the server cache take no service time.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServiceTimeConfig {
+    /// Seed for the jitter. Runs with the same seed and requests get the same service times.
+    pub seed: Option<u64>,
+    pub base_ms: f64,
+    pub jitter: Jitter,
+}
+
+impl ServiceTimeConfig {
+    /// Returns true if queries take any simulated time.
+    pub fn is_enabled(&self) -> bool {
+        self.base_ms != 0.0 || !matches!(self.jitter, Jitter::None)
+    }
+
+    /// Check that the base time is not negative and the jitter distribution is valid.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.base_ms.is_finite() && self.base_ms >= 0.0) {
+            return Err(format!("Invalid base service time: {}", self.base_ms));
+        }
+        self.jitter.validate()
+    }
+}
+
+/// Samples the simulated service time of the queries on a server.
+pub struct ServiceTimeModel {
+    config: ServiceTimeConfig,
+    seed: u64,
+}
+
+impl ServiceTimeModel {
+    /// Create a model from a validated configuration.
+    ///
+    /// Without a seed in the configuration a random seed is used.
+    pub fn new(config: ServiceTimeConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        ServiceTimeModel { config, seed }
+    }
+
+    /// Seed used for the jitter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sample the service time of a request, which only depends on the seed and the request key.
+    pub fn sample(&self, request_key: u64) -> Duration {
+        let mut rng =
+            StdRng::seed_from_u64(self.seed ^ request_key.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        to_delay(self.config.base_ms + self.config.jitter.sample(&mut rng))
+    }
+}
+
+/// Longest simulated delay. Samples from a heavy tail are capped to it.
+const MAX_DELAY_MS: f64 = 3_600_000.0;
+
+/// Convert a sampled delay in milliseconds to a duration between 0 and `MAX_DELAY_MS`.
+///
+/// Negative and NaN samples give no delay.
+fn to_delay(ms: f64) -> Duration {
+    if ms.is_nan() {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(ms.clamp(0.0, MAX_DELAY_MS) / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> LatencyConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn jittered() -> LatencyConfig {
+        config(
+            r#"
+            seed = 7
+            same_zone = { base_ms = 10, jitter = { distribution = "uniform", min_ms = 0, max_ms = 50 } }
+            cross_zone = { base_ms = 100, jitter = { distribution = "pareto", scale_ms = 5, shape = 1.5 } }
+            "#,
+        )
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_delays() {
+        let a = LatencyModel::new(jittered());
+        let b = LatencyModel::new(jittered());
+
+        for key in 0..100 {
+            assert_eq!(a.sample(1, 2, key), b.sample(1, 2, key));
+            assert_eq!(a.sample(1, 1, key), b.sample(1, 1, key));
+        }
+    }
+
+    #[test]
+    fn delays_depend_on_the_request_key_and_the_zones() {
+        let model = LatencyModel::new(jittered());
+
+        let by_key: Vec<Duration> = (0..20).map(|key| model.sample(1, 1, key)).collect();
+        assert!(by_key.iter().any(|delay| *delay != by_key[0]));
+
+        let forward: Vec<Duration> = (0..20).map(|key| model.sample(1, 2, key)).collect();
+        let backward: Vec<Duration> = (0..20).map(|key| model.sample(2, 1, key)).collect();
+        assert_ne!(forward, backward);
+    }
+
+    #[test]
+    fn delays_stay_within_the_jitter_range() {
+        let model = LatencyModel::new(jittered());
+
+        for key in 0..200 {
+            let delay = model.sample(3, 3, key);
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(60));
+            assert!(model.sample(3, 4, key) >= Duration::from_millis(105));
+        }
+    }
+
+    #[test]
+    fn links_override_the_defaults_and_can_be_bidirectional() {
+        let model = LatencyModel::new(config(
+            r#"
+            [[links]]
+            from = 1
+            to = 2
+            base_ms = 20
+
+            [[links]]
+            from = 3
+            to = 4
+            bidirectional = true
+            base_ms = 30
+
+            [[links]]
+            from = 4
+            to = 3
+            base_ms = 40
+            "#,
+        ));
+
+        assert_eq!(model.sample(1, 2, 0), Duration::from_millis(20));
+        assert_eq!(model.sample(2, 1, 0), Duration::from_millis(85));
+        assert_eq!(model.sample(2, 2, 0), Duration::from_millis(40));
+        assert_eq!(model.sample(3, 4, 0), Duration::from_millis(30));
+        assert_eq!(model.sample(4, 3, 0), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn rejects_invalid_links() {
+        let any_zone = |_| true;
+
+        assert!(config("same_zone = { base_ms = inf }")
+            .validate(any_zone)
+            .is_err());
+        assert!(config("cross_zone = { base_ms = -1 }")
+            .validate(any_zone)
+            .is_err());
+        assert!(config(
+            r#"cross_zone = { base_ms = 1, jitter = { distribution = "uniform", min_ms = 5, max_ms = 1 } }"#
+        )
+        .validate(any_zone)
+        .is_err());
+        assert!(config("links = [{ from = 1, to = 9, base_ms = 1 }]")
+            .validate(|zone| zone < 5)
+            .is_err());
+        assert!(jittered().validate(any_zone).is_ok());
+    }
+
+    #[test]
+    fn caps_heavy_tails() {
+        let model = LatencyModel::new(config(
+            r#"
+            seed = 1
+            same_zone = { base_ms = 0, jitter = { distribution = "pareto", scale_ms = 1e300, shape = 0.01 } }
+            "#,
+        ));
+
+        for key in 0..20 {
+            assert_eq!(model.sample(1, 1, key), to_delay(MAX_DELAY_MS));
+        }
+    }
+
+    #[test]
+    fn service_times_are_seeded() {
+        let config: ServiceTimeConfig = toml::from_str(
+            r#"
+            seed = 3
+            base_ms = 2
+            jitter = { distribution = "normal", mean_ms = 0, std_dev_ms = 5 }
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let a = ServiceTimeModel::new(config.clone());
+        let b = ServiceTimeModel::new(config);
+
+        for key in 0..100 {
+            assert_eq!(a.sample(key), b.sample(key));
+        }
+        // Negative samples of the normal jitter give no service time
+        assert!((0..100).any(|key| a.sample(key) == Duration::ZERO));
+    }
+}
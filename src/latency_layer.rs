@@ -0,0 +1,157 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use http::{HeaderMap, HeaderValue, Request, Response};
+use tower::{Layer, Service};
+
+use crate::latency::LatencyModel;
+
+/// Response header with the total simulated network delay of a call in milliseconds.
+///
+/// Each latency layer on the path adds its delay to the value already in the header.
+pub const NETWORK_DELAY_HEADER: &str = "network-delay";
+
+/// Request header used as the key for the jitter of a call.
+///
+/// Calls without the header get a key from a counter, so their delays are not reproducible.
+pub const REQUEST_KEY_HEADER: &str = "request_key";
+
+/// Request header with the zone of the client.
+pub const CLIENT_ZONE_HEADER: &str = "client_zone";
+
+/// Set once a client layer has warned that the server also injects latency.
+static WARNED_DOUBLE_LATENCY: AtomicBool = AtomicBool::new(false);
+
+/// Which end of the call the layer is attached to.
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    /// On the channel of a client in `from` zone, connected to a server in `to` zone.
+    Client { from: u32, to: u32 },
+    /// On a server in the given zone. The client zone is read from the `client_zone` header.
+    Server { zone: u32 },
+}
+
+/// Tower layer simulating network latency between zones.
+///
+/// The request is delayed by the link from the client zone to the server zone,
+/// and the response by the link back. The total delay is reported in the `network-delay` header of the response.
+/// Attach it to either the client channel or the server, not both, as the delays would be added twice.
+/// Client layers warn once per process when a response already has a network delay from a server layer.
+#[derive(Clone)]
+pub struct LatencyLayer {
+    model: Arc<LatencyModel>,
+    side: Side,
+    counter: Arc<AtomicU64>,
+}
+
+impl LatencyLayer {
+    /// Layer for the channel of a client in `from` zone, connected to a server in `to` zone.
+    pub fn client(model: Arc<LatencyModel>, from: u32, to: u32) -> Self {
+        LatencyLayer {
+            model,
+            side: Side::Client { from, to },
+            counter: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Layer for a server in the given zone.
+    pub fn server(model: Arc<LatencyModel>, zone: u32) -> Self {
+        LatencyLayer {
+            model,
+            side: Side::Server { zone },
+            counter: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl<S> Layer<S> for LatencyLayer {
+    type Service = LatencyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LatencyService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by `LatencyLayer`.
+#[derive(Clone)]
+pub struct LatencyService<S> {
+    inner: S,
+    layer: LatencyLayer,
+}
+
+impl<S> LatencyService<S> {
+    /// Get the client and server zone of a request.
+    fn zones(&self, headers: &HeaderMap) -> (u32, u32) {
+        match self.layer.side {
+            Side::Client { from, to } => (from, to),
+            Side::Server { zone } => {
+                let from = header_value(headers, CLIENT_ZONE_HEADER).unwrap_or(zone as u64);
+                (from as u32, zone)
+            }
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for LatencyService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // Use the service that was polled ready, and leave a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let (from, to) = self.zones(request.headers());
+        let key = header_value(request.headers(), REQUEST_KEY_HEADER)
+            .unwrap_or_else(|| self.layer.counter.fetch_add(1, Ordering::Relaxed));
+        let model = self.layer.model.clone();
+        let is_client = matches!(self.layer.side, Side::Client { .. });
+
+        Box::pin(async move {
+            // Separate keys for each direction, so a same zone call gets two samples
+            let request_delay = model.delay(from, to, key.wrapping_mul(2)).await;
+            let mut response = inner.call(request).await?;
+            let response_delay = model.delay(to, from, key.wrapping_mul(2) + 1).await;
+
+            let server_delay = header_value(response.headers(), NETWORK_DELAY_HEADER).unwrap_or(0);
+            if is_client && server_delay > 0 && !WARNED_DOUBLE_LATENCY.swap(true, Ordering::Relaxed)
+            {
+                println!("[WARN] The server also injects network latency, so every delay is added twice. Run the client with --no-latency when the servers run with --latency");
+            }
+
+            let injected = request_delay + response_delay;
+            add_network_delay(response.headers_mut(), injected);
+
+            Ok(response)
+        })
+    }
+}
+
+/// Parse a numeric header value.
+pub(crate) fn header_value(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse::<u64>().ok()
+}
+
+/// Add the delay to the network delay header of a response.
+fn add_network_delay(headers: &mut HeaderMap, delay: Duration) {
+    let total = header_value(headers, NETWORK_DELAY_HEADER).unwrap_or(0) + delay.as_millis() as u64;
+    headers.insert(NETWORK_DELAY_HEADER, HeaderValue::from(total));
+}
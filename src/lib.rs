@@ -0,0 +1,26 @@
+//! Shared code for the server, client and proxy binaries.
+
+pub mod arrivals;
+pub mod cache;
+pub mod client_log;
+pub mod dataset;
+pub mod db_pool;
+pub mod fault_layer;
+pub mod faults;
+pub mod geo_index;
+pub mod latency;
+pub mod latency_layer;
+pub mod memory_transport;
+pub mod plot;
+pub mod proto;
+pub mod request_file;
+pub mod response_cache;
+pub mod rpc_error;
+pub mod scenario;
+pub mod shutdown;
+pub mod stat_client;
+pub mod stat_server;
+pub mod stats;
+pub mod synthetic;
+pub mod topology;
+pub mod workload;
@@ -0,0 +1,266 @@
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use rs_distributed_stats::proto::stat_service_v2 as v2;
+use rs_distributed_stats::proto::stat_service_v2::stat_methods_client::StatMethodsClient as StatMethodsV2Client;
+use rs_distributed_stats::rpc_error::ErrorDetails;
+use rs_distributed_stats::topology::{Topology, DEFAULT_TOPOLOGY_PATH};
+use serde::Serialize;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Endpoint;
+use tonic::{Request, Status};
+
+/// Format the city records are written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// CSV with a header row.
+    Csv,
+    /// JSON array with one city object per line.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("Unknown output format: {} (csv or json)", other)),
+        }
+    }
+}
+
+/// Options of the listing given as command-line arguments.
+struct ListOptions {
+    /// Zone the request is sent from. The server of this zone is used unless a proxy is given.
+    zone: u32,
+    topology_path: String,
+    /// Address of the proxy (with protocol). The request is sent directly to a server if not set.
+    proxy_addr: Option<String>,
+    request: v2::ListCitiesRequest,
+    format: OutputFormat,
+    /// File the records are written to. Standard output is used if not set.
+    output: Option<String>,
+}
+
+/// A city written to the output, with the columns of the `cities` table.
+#[derive(Serialize)]
+struct CityRecord {
+    geoname_id: i64,
+    name: String,
+    ascii_name: String,
+    country_code: String,
+    country: String,
+    admin1_code: Option<String>,
+    population: i64,
+    elevation: Option<i64>,
+    timezone: Option<String>,
+    latitude: f64,
+    longitude: f64,
+}
+
+impl From<v2::City> for CityRecord {
+    fn from(city: v2::City) -> Self {
+        CityRecord {
+            geoname_id: city.geoname_id,
+            name: city.name,
+            ascii_name: city.ascii_name,
+            country_code: city.country_code,
+            country: city.country,
+            admin1_code: city.admin1_code,
+            population: city.population,
+            elevation: city.elevation,
+            timezone: city.timezone,
+            latitude: city.latitude,
+            longitude: city.longitude,
+        }
+    }
+}
+
+/// Writes the city records one at a time, so the output does not have to fit in memory.
+enum CityWriter {
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    Json { out: Box<dyn Write>, written: usize },
+}
+
+impl CityWriter {
+    fn new(format: OutputFormat, out: Box<dyn Write>) -> Self {
+        match format {
+            OutputFormat::Csv => CityWriter::Csv(Box::new(csv::Writer::from_writer(out))),
+            OutputFormat::Json => CityWriter::Json { out, written: 0 },
+        }
+    }
+
+    fn write(&mut self, city: &CityRecord) -> Result<(), Box<dyn Error>> {
+        match self {
+            CityWriter::Csv(writer) => writer.serialize(city)?,
+            CityWriter::Json { out, written } => {
+                out.write_all(if *written == 0 { b"[\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut *out, city)?;
+                *written += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the JSON array and flush the output.
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            CityWriter::Csv(mut writer) => writer.flush()?,
+            CityWriter::Json { mut out, written } => {
+                out.write_all(if written == 0 { b"[]\n" } else { b"\n]\n" })?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describe a failed call, with the error reason the server sent.
+fn describe_failure(status: &Status) -> String {
+    let details = ErrorDetails::from_status(status);
+    let mut description = format!(
+        "ListCities failed with {:?}: {}",
+        status.code(),
+        status.message()
+    );
+    if let Some(reason) = details.reason {
+        description.push_str(&format!(" [{}]", reason));
+    }
+    description
+}
+
+/// Parse the zone and the optional flags.
+///
+/// `--topology <path>` and `--proxy <addr>` choose where the request is sent, as for the client.
+/// `--country <name>`, `--min-population <n>`, `--max-population <n>` and `--admin1 <code>` filter the cities,
+/// and `--batch-size <n>` sets the number of cities per streamed response.
+/// `--format <csv | json>` (CSV by default) and `--output <path>` (standard output by default) set where the cities are written.
+fn parse_options(args: &[String]) -> Result<ListOptions, Box<dyn Error>> {
+    let mut zone: Option<u32> = None;
+    let mut topology_path = String::from(DEFAULT_TOPOLOGY_PATH);
+    let mut proxy_addr: Option<String> = None;
+    let mut request = v2::ListCitiesRequest::default();
+    let mut population = v2::PopulationRange::default();
+    let mut format = OutputFormat::Csv;
+    let mut output: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") && zone.is_none() {
+            zone = Some(arg.parse::<u32>()?);
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", arg))?;
+
+        match arg.as_str() {
+            "--topology" => topology_path = value.clone(),
+            "--proxy" => proxy_addr = Some(value.clone()),
+            "--country" => request.country = Some(value.clone()),
+            "--min-population" => population.min = Some(value.parse::<i64>()?),
+            "--max-population" => population.max = Some(value.parse::<i64>()?),
+            "--admin1" => request.admin1_code = Some(value.clone()),
+            "--batch-size" => request.batch_size = Some(value.parse::<u32>()?),
+            "--format" => format = value.parse::<OutputFormat>()?,
+            "--output" => output = Some(value.clone()),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    if population.min.is_some() || population.max.is_some() {
+        request.population = Some(population);
+    }
+
+    Ok(ListOptions {
+        zone: zone.ok_or("Missing zone")?,
+        topology_path,
+        proxy_addr,
+        request,
+        format,
+        output,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.iter().any(|arg| arg == "--help") {
+        eprintln!(
+            "Usage: {} <zone> [--topology <path>] [--proxy <addr>] [--country <name>] [--min-population <n>] [--max-population <n>] [--admin1 <code>] [--batch-size <n>] [--format <csv | json>] [--output <path>]",
+            args[0]
+        );
+        return Ok(());
+    }
+    let options = parse_options(&args[1..])?;
+
+    // Send the request to the proxy, or to a server of the zone
+    let server_addr = match &options.proxy_addr {
+        Some(proxy_addr) => proxy_addr.clone(),
+        None => {
+            let topology = Topology::load(&options.topology_path)?;
+            topology
+                .pick_server(options.zone, 0)
+                .map(|server| server.uri())
+                .ok_or_else(|| format!("No server in zone {}", options.zone))?
+        }
+    };
+    let channel = Endpoint::from_shared(server_addr.clone())?
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", server_addr, e))?;
+    let mut client = StatMethodsV2Client::new(channel);
+
+    let mut request = Request::new(options.request.clone());
+    request
+        .metadata_mut()
+        .insert("client_zone", MetadataValue::from(options.zone));
+
+    let mut stream = client
+        .list_cities(request)
+        .await
+        .map_err(|status| describe_failure(&status))?
+        .into_inner();
+
+    // Logs go to standard error, so they do not mix with the records written to standard output
+    let out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut writer = CityWriter::new(options.format, out);
+
+    // Each response is written before the next one is read, so a slow output slows down the server
+    let mut cities = 0;
+    let mut batches = 0;
+    while let Some(response) = stream
+        .message()
+        .await
+        .map_err(|status| describe_failure(&status))?
+    {
+        batches += 1;
+        for city in response.cities {
+            writer.write(&CityRecord::from(city))?;
+            cities += 1;
+        }
+    }
+    writer.finish()?;
+
+    eprintln!(
+        "[INFO] Listed {} cities in {} batches from {}",
+        cities, batches, server_addr
+    );
+    if let Some(path) = &options.output {
+        eprintln!("[INFO] Cities written to {}", path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,435 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::io;
+
+use rs_distributed_stats::dataset::{write_database, City, DEFAULT_DATABASE_PATH};
+use serde::Deserialize;
+
+/// Number of rejected rows printed before only counting them.
+const PRINTED_REJECTIONS: usize = 10;
+
+/// Row of the geonames-all-cities CSV export, before validation.
+///
+/// Columns not used by the database are ignored.
+#[derive(Debug, Deserialize)]
+struct RawCity {
+    #[serde(rename = "Geoname ID")]
+    geoname_id: String,
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "ASCII Name", default)]
+    ascii_name: String,
+    #[serde(rename = "Country Code", default)]
+    country_code: String,
+    #[serde(rename = "Country name EN")]
+    country: String,
+    #[serde(rename = "Admin1 Code", default)]
+    admin1_code: String,
+    #[serde(rename = "Population")]
+    population: String,
+    #[serde(rename = "Elevation", default)]
+    elevation: String,
+    #[serde(rename = "Timezone", default)]
+    timezone: String,
+    /// Latitude and longitude, separated by a comma.
+    #[serde(rename = "Coordinates")]
+    coordinates: String,
+}
+
+/// Reason a row was not loaded.
+#[derive(Debug)]
+struct Rejection {
+    /// Column with the invalid value, or `row` if the row could not be read.
+    field: &'static str,
+    message: String,
+}
+
+impl Rejection {
+    fn new(field: &'static str, message: String) -> Self {
+        Rejection { field, message }
+    }
+}
+
+impl RawCity {
+    /// Check the values of the row and convert them to their column types.
+    fn validate(self) -> Result<City, Rejection> {
+        let geoname_id = self.geoname_id.trim().parse::<i64>().map_err(|_| {
+            Rejection::new("Geoname ID", format!("Invalid id '{}'", self.geoname_id))
+        })?;
+
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err(Rejection::new("Name", String::from("Missing name")));
+        }
+
+        let country = self.country.trim().to_string();
+        if country.is_empty() {
+            return Err(Rejection::new(
+                "Country name EN",
+                String::from("Missing country"),
+            ));
+        }
+
+        let population = match self.population.trim().parse::<i64>() {
+            Ok(population) if population >= 0 => population,
+            _ => {
+                return Err(Rejection::new(
+                    "Population",
+                    format!("Invalid population '{}'", self.population),
+                ))
+            }
+        };
+
+        let elevation = match self.elevation.trim() {
+            "" => None,
+            value => Some(value.parse::<i64>().map_err(|_| {
+                Rejection::new("Elevation", format!("Invalid elevation '{}'", value))
+            })?),
+        };
+
+        let (latitude, longitude) = parse_coordinates(&self.coordinates).ok_or_else(|| {
+            Rejection::new(
+                "Coordinates",
+                format!("Invalid coordinates '{}'", self.coordinates),
+            )
+        })?;
+
+        let ascii_name = match self.ascii_name.trim() {
+            "" => name.clone(),
+            value => value.to_string(),
+        };
+
+        Ok(City {
+            geoname_id,
+            name,
+            ascii_name,
+            country_code: self.country_code.trim().to_string(),
+            country,
+            admin1_code: non_empty(self.admin1_code),
+            population,
+            elevation,
+            timezone: non_empty(self.timezone),
+            latitude,
+            longitude,
+        })
+    }
+}
+
+/// Parse coordinates given as `<latitude>, <longitude>`.
+fn parse_coordinates(value: &str) -> Option<(f64, f64)> {
+    let (latitude, longitude) = value.split_once(',')?;
+    let latitude = latitude.trim().parse::<f64>().ok()?;
+    let longitude = longitude.trim().parse::<f64>().ok()?;
+
+    if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) {
+        Some((latitude, longitude))
+    } else {
+        None
+    }
+}
+
+/// Trimmed value, or `None` if it is empty.
+fn non_empty(value: String) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        value => Some(value.to_string()),
+    }
+}
+
+/// Cities read from the CSV export, with the rows that were rejected.
+struct LoadedCities {
+    /// Valid cities, in file order.
+    cities: Vec<City>,
+    /// Number of rows read, valid or not.
+    rows: usize,
+    /// Line number and reason of every rejected row, in file order.
+    rejected: Vec<(u64, Rejection)>,
+}
+
+/// Reader settings of the geonames export, which is separated by semicolons.
+fn export_reader() -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.delimiter(b';').flexible(true);
+    builder
+}
+
+/// Read and validate every row of the CSV export.
+///
+/// Rows that cannot be read, have an invalid value, or repeat the Geoname ID of an earlier row are rejected.
+fn read_cities<R: io::Read>(reader: &mut csv::Reader<R>) -> Result<LoadedCities, csv::Error> {
+    let headers = reader.headers()?.clone();
+
+    let mut loaded = LoadedCities {
+        cities: Vec::new(),
+        rows: 0,
+        rejected: Vec::new(),
+    };
+    let mut ids = HashSet::new();
+
+    for record in reader.records() {
+        loaded.rows += 1;
+
+        let result = match record {
+            Ok(record) => {
+                let line = record.position().map_or(0, |position| position.line());
+                let city = record
+                    .deserialize::<RawCity>(Some(&headers))
+                    .map_err(|e| Rejection::new("row", e.to_string()))
+                    .and_then(RawCity::validate)
+                    .and_then(|city| match ids.insert(city.geoname_id) {
+                        true => Ok(city),
+                        false => Err(Rejection::new(
+                            "Geoname ID",
+                            format!("Duplicate id {}", city.geoname_id),
+                        )),
+                    });
+                (line, city)
+            }
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                (line, Err(Rejection::new("row", e.to_string())))
+            }
+        };
+
+        match result {
+            (_, Ok(city)) => loaded.cities.push(city),
+            (line, Err(rejection)) => loaded.rejected.push((line, rejection)),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// Write the line number, column and reason of every rejected row as CSV with a header.
+fn write_rejected<W: io::Write>(
+    mut writer: csv::Writer<W>,
+    rejected: &[(u64, Rejection)],
+) -> Result<(), Box<dyn Error>> {
+    writer.write_record(["line", "column", "reason"])?;
+    for (line, rejection) in rejected {
+        writer.write_record([
+            line.to_string(),
+            rejection.field.to_string(),
+            rejection.message.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Options of the loader given as command-line flags.
+struct LoadOptions {
+    /// Path of the database to write.
+    output: String,
+    /// File to write every rejected row to.
+    rejected: Option<String>,
+}
+
+/// Parse the optional loader flags.
+///
+/// `--output <path>` writes the database to the given path instead of `db/city_database.db`.
+/// `--rejected <path>` writes the line number, column and reason of every rejected row to a CSV file.
+fn parse_options(flags: &[String]) -> Result<LoadOptions, Box<dyn Error>> {
+    let mut output = String::from(DEFAULT_DATABASE_PATH);
+    let mut rejected = None;
+
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", flag))?;
+
+        match flag.as_str() {
+            "--output" => output = value.clone(),
+            "--rejected" => rejected = Some(value.clone()),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    Ok(LoadOptions { output, rejected })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <CSV path> [--output <path>] [--rejected <path>]",
+            args[0]
+        );
+        return Ok(());
+    }
+    let csv_path = &args[1];
+    let options = parse_options(&args[2..])?;
+
+    let mut reader = export_reader().from_path(csv_path)?;
+    let loaded = read_cities(&mut reader)?;
+
+    // Print the first rejected rows, and count them per column
+    let mut rejections: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for (index, (line, rejection)) in loaded.rejected.iter().enumerate() {
+        if index < PRINTED_REJECTIONS {
+            println!(
+                "[WARN] Rejected line {} ({}): {}",
+                line, rejection.field, rejection.message
+            );
+        }
+        *rejections.entry(rejection.field).or_default() += 1;
+    }
+
+    if let Some(path) = &options.rejected {
+        write_rejected(csv::Writer::from_path(path)?, &loaded.rejected)?;
+    }
+
+    // Report the rejected rows per column
+    let rejected: usize = rejections.values().sum();
+    println!(
+        "[INFO] Read {} rows: {} valid, {} rejected",
+        loaded.rows,
+        loaded.cities.len(),
+        rejected
+    );
+    for (field, count) in &rejections {
+        println!("[INFO]     {}: {} rejected", field, count);
+    }
+
+    let written = write_database(&options.output, loaded.cities)?;
+    println!("[INFO] Wrote {} cities to {}", written, options.output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Geoname ID;Name;ASCII Name;Country Code;Country name EN;Admin1 Code;Population;Elevation;Timezone;Coordinates";
+
+    /// A valid row of Oslo.
+    fn raw_city() -> RawCity {
+        RawCity {
+            geoname_id: String::from("3143244"),
+            name: String::from("Oslo"),
+            ascii_name: String::from("Oslo"),
+            country_code: String::from("NO"),
+            country: String::from("Norway"),
+            admin1_code: String::from("12"),
+            population: String::from("580000"),
+            elevation: String::new(),
+            timezone: String::from("Europe/Oslo"),
+            coordinates: String::from("59.91273, 10.74609"),
+        }
+    }
+
+    fn read(rows: &[&str]) -> LoadedCities {
+        let data = std::iter::once(HEADER)
+            .chain(rows.iter().copied())
+            .collect::<Vec<_>>()
+            .join("\n");
+        read_cities(&mut export_reader().from_reader(data.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn parses_coordinates_in_range() {
+        let cases = [
+            ("59.9, 10.7", Some((59.9, 10.7))),
+            (" -33.87 ,151.21 ", Some((-33.87, 151.21))),
+            ("90, -180", Some((90.0, -180.0))),
+            ("90.1, 0", None),
+            ("0, 180.5", None),
+            ("59.9; 10.7", None),
+            ("59.9", None),
+            ("north, east", None),
+            ("", None),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(parse_coordinates(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values_with_their_column() {
+        type Change = fn(&mut RawCity);
+        let cases: [(Change, &str); 8] = [
+            (|city| city.geoname_id = String::from("31a"), "Geoname ID"),
+            (|city| city.name = String::from("  "), "Name"),
+            (|city| city.country = String::new(), "Country name EN"),
+            (|city| city.population = String::from("-1"), "Population"),
+            (|city| city.population = String::from("many"), "Population"),
+            (|city| city.elevation = String::from("high"), "Elevation"),
+            (
+                |city| city.coordinates = String::from("59.9"),
+                "Coordinates",
+            ),
+            (
+                |city| city.coordinates = String::from("100, 10"),
+                "Coordinates",
+            ),
+        ];
+
+        for (change, field) in cases {
+            let mut city = raw_city();
+            change(&mut city);
+            assert_eq!(city.validate().unwrap_err().field, field);
+        }
+    }
+
+    #[test]
+    fn fills_optional_columns() {
+        let mut city = raw_city();
+        city.ascii_name = String::new();
+        city.admin1_code = String::from(" ");
+        city.elevation = String::from("23");
+
+        let city = city.validate().unwrap();
+        assert_eq!(city.ascii_name, "Oslo");
+        assert_eq!(city.admin1_code, None);
+        assert_eq!(city.elevation, Some(23));
+        assert_eq!((city.latitude, city.longitude), (59.91273, 10.74609));
+    }
+
+    #[test]
+    fn rejects_duplicate_ids_and_invalid_rows_with_line_numbers() {
+        let loaded = read(&[
+            "1;Oslo;Oslo;NO;Norway;12;580000;;Europe/Oslo;59.9, 10.7",
+            "2;Bergen;Bergen;NO;Norway;46;285000;;Europe/Oslo;60.4, 5.3",
+            "1;Oslo;Oslo;NO;Norway;12;580000;;Europe/Oslo;59.9, 10.7",
+            "3;Nowhere;Nowhere;NO;Norway;;10;;;north, east",
+            "4;Short",
+        ]);
+
+        assert_eq!(loaded.rows, 5);
+        let ids: Vec<i64> = loaded.cities.iter().map(|city| city.geoname_id).collect();
+        assert_eq!(ids, [1, 2]);
+
+        let rejected: Vec<(u64, &str)> = loaded
+            .rejected
+            .iter()
+            .map(|(line, rejection)| (*line, rejection.field))
+            .collect();
+        assert_eq!(
+            rejected,
+            [(4, "Geoname ID"), (5, "Coordinates"), (6, "row")]
+        );
+        assert_eq!(loaded.rejected[0].1.message, "Duplicate id 1");
+    }
+
+    #[test]
+    fn writes_rejected_rows_as_csv() {
+        let loaded = read(&[
+            "1;Oslo;Oslo;NO;Norway;12;580000;;Europe/Oslo;59.9, 10.7",
+            "1;Oslo;Oslo;NO;Norway;12;580000;;Europe/Oslo;59.9, 10.7",
+            "2;Bergen;Bergen;NO;Norway;46;lots;;Europe/Oslo;60.4, 5.3",
+        ]);
+
+        let mut output = Vec::new();
+        write_rejected(csv::Writer::from_writer(&mut output), &loaded.rejected).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "line,column,reason\n3,Geoname ID,Duplicate id 1\n4,Population,Invalid population 'lots'\n"
+        );
+    }
+}
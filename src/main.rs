@@ -1,5 +1,2 @@
-pub mod client;
-pub mod server;
-
 // NOTE: See readme.md for how to run the server and client binaries.
 fn main() {}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use hyper_util::rt::TokioIo;
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc;
+use tokio_stream::Stream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+
+/// Buffer size of each direction of an in-memory connection, in bytes.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// In-memory network connecting clients to servers running in the same process.
+///
+/// Servers listen on the socket address from the topology, but connections are
+/// in-memory duplex streams instead of TCP sockets, so no ports are used.
+#[derive(Default)]
+pub struct MemoryNetwork {
+    /// Incoming connections of each listening address.
+    listeners: Mutex<HashMap<String, mpsc::UnboundedSender<DuplexStream>>>,
+}
+
+/// Connections accepted on an address of a `MemoryNetwork`, served with `serve_with_incoming`.
+pub struct MemoryListener {
+    connections: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl Stream for MemoryListener {
+    type Item = io::Result<DuplexStream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.connections
+            .poll_recv(cx)
+            .map(|connection| connection.map(Ok))
+    }
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listen on the given address, without protocol.
+    ///
+    /// Fails if another listener is still open on the address.
+    pub fn bind(&self, addr: &str) -> io::Result<MemoryListener> {
+        let mut listeners = self.listeners.lock().unwrap();
+        if listeners
+            .get(addr)
+            .is_some_and(|sender| !sender.is_closed())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("Address {} is already in use", addr),
+            ));
+        }
+
+        let (sender, connections) = mpsc::unbounded_channel();
+        listeners.insert(addr.to_string(), sender);
+        Ok(MemoryListener { connections })
+    }
+
+    /// Open a connection to the listener on the given address, without protocol.
+    pub fn connect(&self, addr: &str) -> io::Result<DuplexStream> {
+        let refused = || {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("Nothing is listening on {}", addr),
+            )
+        };
+
+        let listeners = self.listeners.lock().unwrap();
+        let sender = listeners.get(addr).ok_or_else(refused)?;
+        let (client, server) = tokio::io::duplex(BUFFER_SIZE);
+        sender.send(server).map_err(|_| refused())?;
+        Ok(client)
+    }
+
+    /// Connect a gRPC channel to the listener on the address of the given URI, e.g. `http://127.0.0.1:51000`.
+    pub async fn channel(
+        self: &Arc<Self>,
+        uri: String,
+    ) -> Result<Channel, tonic::transport::Error> {
+        let network = self.clone();
+        Endpoint::from_shared(uri)?
+            .connect_with_connector(service_fn(move |uri: Uri| {
+                let network = network.clone();
+                async move {
+                    let addr = uri.authority().map(|authority| authority.as_str());
+                    let connection = network.connect(addr.unwrap_or_default())?;
+                    Ok::<_, io::Error>(TokioIo::new(connection))
+                }
+            }))
+            .await
+    }
+}
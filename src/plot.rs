@@ -0,0 +1,297 @@
+use std::fmt::Write;
+
+use crate::stats::{quantile, BoxStats};
+
+/// Width of a chart in pixels.
+const WIDTH: f64 = 720.0;
+/// Height of a chart in pixels.
+const HEIGHT: f64 = 420.0;
+/// Space around the plot area for the title, labels and axes: top, right, bottom and left.
+const MARGIN: (f64, f64, f64, f64) = (40.0, 20.0, 60.0, 70.0);
+/// Colors of the groups, repeated when there are more groups.
+const COLORS: [&str; 8] = [
+    "#4c72b0", "#dd8452", "#55a868", "#c44e52", "#8172b3", "#937860", "#da8bc3", "#8c8c8c",
+];
+/// Number of points on each CDF line.
+const CDF_POINTS: usize = 200;
+
+/// A named set of measurements drawn as one box or one line.
+pub struct Series<'a> {
+    pub name: String,
+    pub values: &'a [u64],
+}
+
+/// Render a box plot of each series as an SVG document.
+///
+/// Boxes span the quartiles with a line at the median, and the whiskers reach the furthest values
+/// within 1.5 times the interquartile range. Outliers are not drawn. Series without values are left out.
+pub fn box_plot_svg(title: &str, y_label: &str, series: &[Series]) -> String {
+    let boxes: Vec<(&str, BoxStats)> = series
+        .iter()
+        .filter_map(|series| Some((series.name.as_str(), BoxStats::from_values(series.values)?)))
+        .collect();
+
+    let max = boxes
+        .iter()
+        .map(|(_, stats)| stats.upper_whisker)
+        .fold(0.0, f64::max);
+    let axis = Axis::new(max);
+
+    let mut svg = begin_svg(title);
+    axis.draw_y(&mut svg, y_label);
+
+    let (top, right, bottom, left) = MARGIN;
+    let slot = (WIDTH - left - right) / boxes.len().max(1) as f64;
+    let plot_bottom = HEIGHT - bottom;
+    let y = |value: f64| plot_bottom - value / axis.max * (HEIGHT - top - bottom);
+
+    for (index, (name, stats)) in boxes.iter().enumerate() {
+        let center = left + slot * (index as f64 + 0.5);
+        let half = (slot * 0.3).min(40.0);
+        let color = COLORS[index % COLORS.len()];
+
+        // Whiskers with caps
+        for (from, to) in [
+            (stats.lower_whisker, stats.q1),
+            (stats.q3, stats.upper_whisker),
+        ] {
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{c:.1}" y1="{:.1}" x2="{c:.1}" y2="{:.1}" stroke="black"/>"#,
+                y(from),
+                y(to),
+                c = center
+            );
+        }
+        for value in [stats.lower_whisker, stats.upper_whisker] {
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="black"/>"#,
+                center - half / 2.0,
+                center + half / 2.0,
+                y = y(value)
+            );
+        }
+
+        // Box from the first to the third quartile, with the median
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" fill-opacity="0.6" stroke="black"/>"#,
+            center - half,
+            y(stats.q3),
+            half * 2.0,
+            (y(stats.q1) - y(stats.q3)).max(1.0),
+            color
+        );
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="black" stroke-width="2"/>"#,
+            center - half,
+            center + half,
+            y = y(stats.median)
+        );
+
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            center,
+            plot_bottom + 20.0,
+            escape(name)
+        );
+    }
+
+    end_svg(svg)
+}
+
+/// Render the cumulative distribution of each series as lines in an SVG document.
+///
+/// Series without values are left out.
+pub fn cdf_svg(title: &str, x_label: &str, series: &[Series]) -> String {
+    let lines: Vec<(&str, Vec<u64>)> = series
+        .iter()
+        .filter(|series| !series.values.is_empty())
+        .map(|series| {
+            let mut sorted = series.values.to_vec();
+            sorted.sort_unstable();
+            (series.name.as_str(), sorted)
+        })
+        .collect();
+
+    let max = lines
+        .iter()
+        .filter_map(|(_, sorted)| sorted.last())
+        .fold(0.0, |max, value| f64::max(max, *value as f64));
+    let axis = Axis::new(max);
+
+    let mut svg = begin_svg(title);
+    let (top, right, bottom, left) = MARGIN;
+    let plot_bottom = HEIGHT - bottom;
+    let x = |value: f64| left + value / axis.max * (WIDTH - left - right);
+    let y = |share: f64| plot_bottom - share * (HEIGHT - top - bottom);
+
+    // Shares on the y axis and times on the x axis
+    for tick in 0..=4 {
+        let share = tick as f64 / 4.0;
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{left}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="#dddddd"/>"##,
+            WIDTH - right,
+            y = y(share)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+            left - 6.0,
+            y(share) + 4.0,
+            share
+        );
+    }
+    for tick in axis.ticks() {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            x(tick),
+            plot_bottom + 18.0,
+            tick
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+        left + (WIDTH - left - right) / 2.0,
+        HEIGHT - 24.0,
+        escape(x_label)
+    );
+
+    for (index, (name, sorted)) in lines.iter().enumerate() {
+        let color = COLORS[index % COLORS.len()];
+        let points: Vec<String> = (0..=CDF_POINTS)
+            .map(|point| {
+                let share = point as f64 / CDF_POINTS as f64;
+                format!("{:.1},{:.1}", x(quantile(sorted, share)), y(share))
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+            points.join(" "),
+            color
+        );
+
+        // Legend in the lower right corner
+        let legend_y = plot_bottom - 20.0 - 18.0 * (lines.len() - 1 - index) as f64;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="12" height="12" fill="{}"/>"#,
+            WIDTH - right - 130.0,
+            legend_y - 10.0,
+            color
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+            WIDTH - right - 112.0,
+            legend_y,
+            escape(name)
+        );
+    }
+
+    end_svg(svg)
+}
+
+/// Value axis from 0 to a round number above the largest value.
+struct Axis {
+    max: f64,
+    step: f64,
+}
+
+impl Axis {
+    /// Choose an axis with about 5 ticks of 1, 2 or 5 times a power of ten, in whole milliseconds.
+    fn new(max_value: f64) -> Self {
+        let max_value = max_value.max(5.0);
+        let rough = max_value / 5.0;
+        let magnitude = 10f64.powf(rough.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|factor| factor * magnitude)
+            .find(|step| *step >= rough)
+            .unwrap_or(10.0 * magnitude);
+        Axis {
+            max: (max_value / step).ceil() * step,
+            step,
+        }
+    }
+
+    fn ticks(&self) -> Vec<f64> {
+        let count = (self.max / self.step).round() as usize;
+        (0..=count).map(|tick| tick as f64 * self.step).collect()
+    }
+
+    /// Draw the grid lines, tick labels and label of a vertical value axis.
+    fn draw_y(&self, svg: &mut String, label: &str) {
+        let (top, right, bottom, left) = MARGIN;
+        let plot_bottom = HEIGHT - bottom;
+        for tick in self.ticks() {
+            let y = plot_bottom - tick / self.max * (HEIGHT - top - bottom);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{left}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="#dddddd"/>"##,
+                WIDTH - right
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+                left - 6.0,
+                y + 4.0,
+                tick
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<text transform="translate(18 {:.1}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            top + (plot_bottom - top) / 2.0,
+            escape(label)
+        );
+    }
+}
+
+/// Start an SVG document with a white background and the title.
+fn begin_svg(title: &str) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="{WIDTH}" height="{HEIGHT}" fill="white"/>"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.1}" y="24" text-anchor="middle" font-size="16">{}</text>"#,
+        WIDTH / 2.0,
+        escape(title)
+    );
+    svg
+}
+
+/// Draw the axis lines over the plot and end the SVG document.
+fn end_svg(mut svg: String) -> String {
+    let (top, right, bottom, left) = MARGIN;
+    let _ = writeln!(
+        svg,
+        r#"<polyline points="{left},{top} {left},{:.1} {:.1},{:.1}" fill="none" stroke="black"/>"#,
+        HEIGHT - bottom,
+        WIDTH - right,
+        HEIGHT - bottom
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Escape text for use in SVG.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,11 @@
+//! Messages and services of the statistics service, generated from `proto/`.
+
+/// Version 1 of the statistics service, generated from `proto/statservice.proto`.
+pub mod stat_service {
+    tonic::include_proto!("statservice");
+}
+
+/// Version 2 of the statistics service, generated from `proto/statservice/v2/statservice.proto`.
+pub mod stat_service_v2 {
+    tonic::include_proto!("statservice.v2");
+}
@@ -1 +1,581 @@
-// TODO:
\ No newline at end of file
+use std::env;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rs_distributed_stats::latency_layer::{NETWORK_DELAY_HEADER, REQUEST_KEY_HEADER};
+use rs_distributed_stats::proto::stat_service::stat_methods_client::StatMethodsClient;
+use rs_distributed_stats::proto::stat_service::stat_methods_server::{
+    StatMethods, StatMethodsServer,
+};
+use rs_distributed_stats::proto::stat_service::{
+    Empty, NumberOfCitiesRequest, NumberOfCitiesResponse, NumberOfCountriesMaxRequest,
+    NumberOfCountriesMaxResponse, NumberOfCountriesRequest, NumberOfCountriesResponse,
+    PopulationRequest, PopulationResponse, RecordsResponse,
+};
+use rs_distributed_stats::proto::stat_service_v2 as v2;
+use rs_distributed_stats::proto::stat_service_v2::stat_methods_client::StatMethodsClient as StatMethodsV2Client;
+use rs_distributed_stats::proto::stat_service_v2::stat_methods_server::{
+    StatMethods as StatMethodsV2, StatMethodsServer as StatMethodsV2Server,
+};
+use rs_distributed_stats::shutdown::shutdown_signal;
+use rs_distributed_stats::topology::{Topology, DEFAULT_TOPOLOGY_PATH};
+use tokio::time::Instant;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{transport::Server, Code, Request, Response, Status, Streaming};
+
+/// Address the proxy listens on if the topology does not give one.
+const PROXY_ADDR: &str = "127.0.0.1:50000";
+
+/// Extra load added to servers outside the client's zone.
+///
+/// A server in another zone is only chosen when its home zone server has at least this many more requests in flight.
+const CROSS_ZONE_PENALTY: usize = 4;
+
+/// How long the load reported by a server is used.
+///
+/// A server is only asked again once it is selected, so a burst it reported must expire for it to be selected again.
+const REPORTED_LOAD_TTL: Duration = Duration::from_secs(1);
+
+/// How long a server whose last call failed with `UNAVAILABLE` is skipped, unless every server is.
+const UNAVAILABLE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Request metadata keys passed from the client to the server.
+///
+/// The request key keeps the simulated delays, faults and service times of a request the same with or without the proxy,
+/// and `grpc-timeout` gives the server the deadline of the client.
+const FORWARDED_REQUEST_METADATA: [&str; 2] = [REQUEST_KEY_HEADER, "grpc-timeout"];
+
+/// Response metadata keys passed from the server back to the client.
+const FORWARDED_METADATA: [&str; 8] = [
+    "execution",
+    "cache",
+    "cache_epoch",
+    "server_id",
+    "server_zone",
+    "in_flight",
+    "sequence",
+    NETWORK_DELAY_HEADER,
+];
+
+/// A `StatServer` the proxy can forward requests to.
+struct Backend {
+    id: u32,
+    zone: u32,
+    in_flight: AtomicUsize,
+    /// Requests in flight on the server after its last response, including those not sent through the proxy,
+    /// and when it was reported.
+    reported_in_flight: Mutex<Option<(usize, Instant)>>,
+    /// When the last call to the server failed with `UNAVAILABLE`, cleared by the next successful call.
+    unavailable_since: Mutex<Option<Instant>>,
+    /// Channel to the server, shared by the clients of both versions of the service.
+    channel: Channel,
+}
+
+impl Backend {
+    fn new(id: u32, zone: u32, channel: Channel) -> Self {
+        Backend {
+            id,
+            zone,
+            in_flight: AtomicUsize::new(0),
+            reported_in_flight: Mutex::new(None),
+            unavailable_since: Mutex::new(None),
+            channel,
+        }
+    }
+
+    /// Load of the server: the requests the proxy has in flight on it,
+    /// or the count the server reported within `REPORTED_LOAD_TTL` if it is busier.
+    fn load(&self, now: Instant) -> usize {
+        let reported = match *self.reported_in_flight.lock().unwrap() {
+            Some((reported, at)) if now.duration_since(at) < REPORTED_LOAD_TTL => reported,
+            _ => 0,
+        };
+        self.in_flight.load(Ordering::SeqCst).max(reported)
+    }
+
+    /// Whether the server is skipped after failing with `UNAVAILABLE` within `UNAVAILABLE_BACKOFF`.
+    fn is_backing_off(&self, now: Instant) -> bool {
+        self.unavailable_since
+            .lock()
+            .unwrap()
+            .is_some_and(|since| now.duration_since(since) < UNAVAILABLE_BACKOFF)
+    }
+
+    /// Record the outcome of a call to the server.
+    ///
+    /// `UNAVAILABLE`, which includes failing to connect, starts the backoff. Any other outcome means the server is up.
+    fn record_outcome(&self, code: Code, now: Instant) {
+        *self.unavailable_since.lock().unwrap() = (code == Code::Unavailable).then_some(now);
+    }
+}
+
+/// Keeps a request counted as in flight on a backend until dropped.
+struct InFlightGuard {
+    backend: Arc<Backend>,
+}
+
+impl InFlightGuard {
+    fn new(backend: Arc<Backend>) -> Self {
+        backend.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard { backend }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.backend.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Zone aware proxy in front of the `StatServer` instances.
+///
+/// Implements the full `StatMethods` service, in version 1 and 2, and forwards each call to the least busy server,
+/// preferring the server in the zone given by the `client_zone` metadata.
+pub struct StatProxy {
+    backends: Vec<Arc<Backend>>,
+}
+
+impl StatProxy {
+    /// Create a proxy with a lazily connected channel to each server in the topology.
+    pub fn new(topology: &Topology) -> Result<Self, tonic::transport::Error> {
+        let mut backends = Vec::new();
+        for server in &topology.servers {
+            let channel = Endpoint::from_shared(server.uri())?.connect_lazy();
+
+            backends.push(Arc::new(Backend::new(server.id, server.zone, channel)));
+        }
+
+        Ok(StatProxy { backends })
+    }
+
+    /// Select the backend with the lowest load.
+    ///
+    /// The load of a backend is the number of requests in flight, as counted by the proxy or reported by the server,
+    /// with `CROSS_ZONE_PENALTY` added when the backend is outside the client zone.
+    /// Backends backing off after an `UNAVAILABLE` failure are only selected when every backend is.
+    /// Ties are resolved in favour of the client zone, then the order of the servers in the topology.
+    fn select_backend(&self, client_zone: Option<u32>) -> Arc<Backend> {
+        let now = Instant::now();
+        let score = |backend: &Backend| {
+            let backing_off = backend.is_backing_off(now);
+            let in_flight = backend.load(now);
+            match client_zone {
+                Some(zone) if zone == backend.zone => (backing_off, in_flight, 0),
+                _ => (backing_off, in_flight + CROSS_ZONE_PENALTY, 1),
+            }
+        };
+
+        self.backends
+            .iter()
+            .min_by_key(|backend| score(backend))
+            .expect("Proxy has no backends")
+            .clone()
+    }
+
+    /// Forward a request to a selected backend and pass the response back.
+    ///
+    /// The `client_zone` and `FORWARDED_REQUEST_METADATA` keys are kept, while `request_zone` is set to the zone of the chosen server.
+    /// The `FORWARDED_METADATA` keys from the server are returned to the client.
+    async fn forward<Req, Res, F, Fut>(
+        &self,
+        request: Request<Req>,
+        call: F,
+    ) -> Result<Response<Res>, Status>
+    where
+        F: FnOnce(Channel, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let client_zone = request
+            .metadata()
+            .get("client_zone")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u32>().ok());
+
+        let backend = self.select_backend(client_zone);
+        let guard = InFlightGuard::new(backend.clone());
+
+        println!(
+            "[INFO] Forwarding request from zone {:?} to server {} in zone {} ({} in flight)",
+            client_zone,
+            backend.id,
+            backend.zone,
+            backend.in_flight.load(Ordering::SeqCst)
+        );
+
+        // Build the forwarded request with the zone metadata
+        let metadata = request.metadata().clone();
+        let mut forwarded = Request::new(request.into_inner());
+        for key in FORWARDED_REQUEST_METADATA {
+            if let Some(value) = metadata.get(key) {
+                forwarded.metadata_mut().insert(key, value.clone());
+            }
+        }
+        if let Some(zone) = client_zone {
+            forwarded
+                .metadata_mut()
+                .insert("client_zone", MetadataValue::from(zone));
+        }
+        forwarded
+            .metadata_mut()
+            .insert("request_zone", MetadataValue::from(backend.zone));
+
+        let result = call(backend.channel.clone(), forwarded).await;
+        drop(guard);
+
+        let code = match &result {
+            Ok(_) => Code::Ok,
+            Err(status) => status.code(),
+        };
+        backend.record_outcome(code, Instant::now());
+
+        let backend_response = match result {
+            Ok(response) => response,
+            Err(status) => {
+                println!(
+                    "[ERROR] Server {} failed request: {}",
+                    backend.id,
+                    status.message()
+                );
+                return Err(status);
+            }
+        };
+
+        // Remember the load the server reported, without the request that just finished
+        let reported = backend_response
+            .metadata()
+            .get("in_flight")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if let Some(reported) = reported {
+            *backend.reported_in_flight.lock().unwrap() =
+                Some((reported.saturating_sub(1), Instant::now()));
+        }
+
+        // Pass the metadata of the server back to the client
+        let metadata = backend_response.metadata().clone();
+        let mut response = Response::new(backend_response.into_inner());
+        for key in FORWARDED_METADATA {
+            if let Some(value) = metadata.get(key) {
+                response.metadata_mut().insert(key, value.clone());
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+#[tonic::async_trait]
+impl StatMethods for StatProxy {
+    async fn get_records_count(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<RecordsResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsClient::new(channel)
+                .get_records_count(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_population_of_country(
+        &self,
+        request: Request<PopulationRequest>,
+    ) -> Result<Response<PopulationResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsClient::new(channel)
+                .get_population_of_country(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_number_of_cities(
+        &self,
+        request: Request<NumberOfCitiesRequest>,
+    ) -> Result<Response<NumberOfCitiesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsClient::new(channel)
+                .get_number_of_cities(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_number_of_countries(
+        &self,
+        request: Request<NumberOfCountriesRequest>,
+    ) -> Result<Response<NumberOfCountriesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsClient::new(channel)
+                .get_number_of_countries(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_number_of_countries_max(
+        &self,
+        request: Request<NumberOfCountriesMaxRequest>,
+    ) -> Result<Response<NumberOfCountriesMaxResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsClient::new(channel)
+                .get_number_of_countries_max(request)
+                .await
+        })
+        .await
+    }
+}
+
+#[tonic::async_trait]
+impl StatMethodsV2 for StatProxy {
+    async fn get_records_count(
+        &self,
+        request: Request<v2::GetRecordsCountRequest>,
+    ) -> Result<Response<v2::GetRecordsCountResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .get_records_count(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_population_of_country(
+        &self,
+        request: Request<v2::GetPopulationOfCountryRequest>,
+    ) -> Result<Response<v2::GetPopulationOfCountryResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .get_population_of_country(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_number_of_cities(
+        &self,
+        request: Request<v2::GetNumberOfCitiesRequest>,
+    ) -> Result<Response<v2::GetNumberOfCitiesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .get_number_of_cities(request)
+                .await
+        })
+        .await
+    }
+
+    async fn get_number_of_countries(
+        &self,
+        request: Request<v2::GetNumberOfCountriesRequest>,
+    ) -> Result<Response<v2::GetNumberOfCountriesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .get_number_of_countries(request)
+                .await
+        })
+        .await
+    }
+
+    async fn find_cities_within_radius(
+        &self,
+        request: Request<v2::FindCitiesWithinRadiusRequest>,
+    ) -> Result<Response<v2::FindCitiesWithinRadiusResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .find_cities_within_radius(request)
+                .await
+        })
+        .await
+    }
+
+    async fn find_nearest_cities(
+        &self,
+        request: Request<v2::FindNearestCitiesRequest>,
+    ) -> Result<Response<v2::FindNearestCitiesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .find_nearest_cities(request)
+                .await
+        })
+        .await
+    }
+
+    async fn find_cities_in_bounding_box(
+        &self,
+        request: Request<v2::FindCitiesInBoundingBoxRequest>,
+    ) -> Result<Response<v2::FindCitiesInBoundingBoxResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .find_cities_in_bounding_box(request)
+                .await
+        })
+        .await
+    }
+
+    async fn rank_cities(
+        &self,
+        request: Request<v2::RankCitiesRequest>,
+    ) -> Result<Response<v2::RankCitiesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel).rank_cities(request).await
+        })
+        .await
+    }
+
+    async fn rank_countries(
+        &self,
+        request: Request<v2::RankCountriesRequest>,
+    ) -> Result<Response<v2::RankCountriesResponse>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel)
+                .rank_countries(request)
+                .await
+        })
+        .await
+    }
+
+    type ListCitiesStream = Streaming<v2::ListCitiesResponse>;
+
+    /// Forward a listing of cities. The backend only counts the request in flight until the stream starts.
+    async fn list_cities(
+        &self,
+        request: Request<v2::ListCitiesRequest>,
+    ) -> Result<Response<Self::ListCitiesStream>, Status> {
+        self.forward(request, |channel, request| async move {
+            StatMethodsV2Client::new(channel).list_cities(request).await
+        })
+        .await
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 2 {
+        eprintln!("Usage: {} [Topology path]", args[0]);
+        return Ok(());
+    }
+    let topology_path = args
+        .get(1)
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_TOPOLOGY_PATH);
+    let topology = Topology::load(topology_path)?;
+
+    let addr = topology.proxy.as_deref().unwrap_or(PROXY_ADDR);
+    let proxy_addr = addr.parse::<SocketAddr>()?;
+
+    // Proxy creation
+    let proxy = Arc::new(StatProxy::new(&topology)?);
+
+    // Health service, reporting the proxy as serving once it listens
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_serving::<StatMethodsServer<StatProxy>>()
+        .await;
+    health_reporter
+        .set_serving::<StatMethodsV2Server<StatProxy>>()
+        .await;
+
+    // Logging that the proxy has started
+    println!("[INFO] Proxy started on {}", addr);
+
+    Server::builder()
+        .add_service(health_service)
+        .add_service(StatMethodsServer::from_arc(proxy.clone()))
+        .add_service(StatMethodsV2Server::from_arc(proxy))
+        .serve_with_shutdown(proxy_addr, shutdown_signal())
+        .await?;
+
+    println!("[INFO] Proxy stopped");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A proxy with one backend per zone given, with ids counted from 1.
+    fn proxy(zones: &[u32]) -> StatProxy {
+        let backends = zones
+            .iter()
+            .enumerate()
+            .map(|(index, zone)| {
+                let channel = Endpoint::from_static("http://127.0.0.1:1").connect_lazy();
+                Arc::new(Backend::new(index as u32 + 1, *zone, channel))
+            })
+            .collect();
+        StatProxy { backends }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn selects_least_busy_server_of_client_zone() {
+        let proxy = proxy(&[1, 1, 2]);
+        proxy.backends[0].in_flight.store(2, Ordering::SeqCst);
+
+        assert_eq!(proxy.select_backend(Some(1)).id, 2);
+        assert_eq!(proxy.select_backend(Some(2)).id, 3);
+        // Without a client zone every server has the penalty
+        assert_eq!(proxy.select_backend(None).id, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn crosses_zones_only_past_the_penalty() {
+        let proxy = proxy(&[1, 2]);
+
+        proxy.backends[0]
+            .in_flight
+            .store(CROSS_ZONE_PENALTY, Ordering::SeqCst);
+        assert_eq!(proxy.select_backend(Some(1)).id, 1);
+
+        proxy.backends[0]
+            .in_flight
+            .store(CROSS_ZONE_PENALTY + 1, Ordering::SeqCst);
+        assert_eq!(proxy.select_backend(Some(1)).id, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reported_load_expires() {
+        let proxy = proxy(&[1, 2]);
+        *proxy.backends[0].reported_in_flight.lock().unwrap() = Some((30, Instant::now()));
+        assert_eq!(proxy.select_backend(Some(1)).id, 2);
+
+        tokio::time::advance(REPORTED_LOAD_TTL).await;
+        assert_eq!(proxy.select_backend(Some(1)).id, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unavailable_server_is_skipped_until_backoff_ends() {
+        let proxy = proxy(&[1, 2]);
+        proxy.backends[0].record_outcome(Code::Unavailable, Instant::now());
+        assert_eq!(proxy.select_backend(Some(1)).id, 2);
+
+        tokio::time::advance(UNAVAILABLE_BACKOFF).await;
+        assert_eq!(proxy.select_backend(Some(1)).id, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn answered_call_ends_backoff() {
+        let proxy = proxy(&[1, 2]);
+        proxy.backends[0].record_outcome(Code::Unavailable, Instant::now());
+        proxy.backends[0].record_outcome(Code::NotFound, Instant::now());
+
+        assert_eq!(proxy.select_backend(Some(1)).id, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn selects_among_all_servers_when_all_are_unavailable() {
+        let proxy = proxy(&[1, 2]);
+        for backend in &proxy.backends {
+            backend.record_outcome(Code::Unavailable, Instant::now());
+        }
+
+        assert_eq!(proxy.select_backend(Some(2)).id, 2);
+    }
+}
@@ -0,0 +1,352 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Value of `format` in the header line of a versioned request file.
+pub const REQUEST_FILE_FORMAT: &str = "rs-distributed-stats/requests";
+
+/// Latest version of the request file format.
+pub const REQUEST_FILE_VERSION: u32 = 2;
+
+/// First line of a versioned request file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestFileHeader {
+    pub format: String,
+    pub version: u32,
+}
+
+impl Default for RequestFileHeader {
+    fn default() -> Self {
+        RequestFileHeader {
+            format: String::from(REQUEST_FILE_FORMAT),
+            version: REQUEST_FILE_VERSION,
+        }
+    }
+}
+
+/// A request in a version 2 request file, one JSON object per line.
+///
+/// Only the parameters used by the RPC have to be given.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestEntry {
+    /// Id of the request, used in the client output. Defaults to the position in the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Function name, as in the text format, e.g. `getNumberofCities`.
+    pub rpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_population: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_population: Option<u64>,
+    /// Zone the request is sent to.
+    pub zone: u32,
+    /// Time to send the request, in milliseconds after the client started. Sent at once if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_at_ms: Option<u64>,
+    /// Time the request may take before it is counted as timed out, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u64>,
+    /// Requests due at the same time are sent in order of priority, highest first.
+    #[serde(default)]
+    pub priority: i32,
+    /// Result the request should return. A different result is reported as a mismatch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<i64>,
+}
+
+impl RequestEntry {
+    /// Convert the entry to the values of a text format line.
+    pub fn to_inputs(&self) -> Result<Vec<String>, String> {
+        let missing = |field: &str| format!("{} needs the field '{}'", self.rpc, field);
+        let country = || self.country.clone().ok_or_else(|| missing("country"));
+        let city_count = || {
+            self.city_count
+                .map(|count| count.to_string())
+                .ok_or_else(|| missing("city_count"))
+        };
+        let min = || {
+            self.min_population
+                .map(|min| min.to_string())
+                .ok_or_else(|| missing("min_population"))
+        };
+        let max = || {
+            self.max_population
+                .map(|max| max.to_string())
+                .ok_or_else(|| missing("max_population"))
+        };
+
+        let mut inputs = vec![self.rpc.clone()];
+        match self.rpc.as_str() {
+            "getPopulationofCountry" => inputs.push(country()?),
+            "getNumberofCities" => inputs.extend([country()?, min()?]),
+            "getNumberofCountries" => inputs.extend([city_count()?, min()?]),
+            "getNumberofCountriesMax" => inputs.extend([city_count()?, min()?, max()?]),
+            unknown => return Err(format!("Unknown function name: {}", unknown)),
+        }
+        inputs.push(format!("Zone:{}", self.zone));
+
+        Ok(inputs)
+    }
+}
+
+/// A request read from a request file, in either format.
+#[derive(Debug, Clone)]
+pub struct PlannedRequest {
+    pub id: String,
+    /// Function name followed by the parameters, as in a text format line.
+    pub inputs: Vec<String>,
+    pub send_at: Option<Duration>,
+    pub deadline: Option<Duration>,
+    pub priority: i32,
+    pub expected: Option<i64>,
+}
+
+impl PlannedRequest {
+    /// Function name of the request.
+    pub fn function(&self) -> &str {
+        &self.inputs[0]
+    }
+}
+
+/// Contents of a request file.
+#[derive(Debug, Default)]
+pub struct RequestFile {
+    /// Version of the format, 1 for the text format.
+    pub version: u32,
+    pub requests: Vec<PlannedRequest>,
+    /// Line number and reason of every line that could not be read.
+    pub rejected: Vec<(usize, String)>,
+}
+
+impl RequestFile {
+    /// Parse a request file.
+    ///
+    /// Files starting with a JSON header line are read as JSON Lines, anything else as the
+    /// whitespace separated text format, where each line is `<function> <parameters...> Zone:<ID>`.
+    pub fn parse(contents: &str) -> Result<RequestFile, String> {
+        let first = contents.lines().find(|line| !line.trim().is_empty());
+        match first {
+            Some(line) if line.trim_start().starts_with('{') => {
+                let header: RequestFileHeader = serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid request file header: {}", e))?;
+                if header.format != REQUEST_FILE_FORMAT {
+                    return Err(format!("Unknown request file format: {}", header.format));
+                }
+                if header.version != REQUEST_FILE_VERSION {
+                    return Err(format!(
+                        "Unsupported request file version: {}",
+                        header.version
+                    ));
+                }
+                Ok(Self::parse_json_lines(contents))
+            }
+            _ => Ok(Self::parse_text(contents)),
+        }
+    }
+
+    /// Parse the text format of version 1.
+    fn parse_text(contents: &str) -> RequestFile {
+        let mut file = RequestFile {
+            version: 1,
+            ..Default::default()
+        };
+
+        for (index, line) in contents.lines().enumerate() {
+            let inputs: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+            if inputs.len() < 3 {
+                file.rejected
+                    .push((index + 1, format!("Line with illegal values: {}", line)));
+                continue;
+            }
+            if let Err(e) = check_inputs(&inputs) {
+                file.rejected.push((index + 1, e));
+                continue;
+            }
+
+            file.requests.push(PlannedRequest {
+                id: file.requests.len().to_string(),
+                inputs,
+                send_at: None,
+                deadline: None,
+                priority: 0,
+                expected: None,
+            });
+        }
+
+        file
+    }
+
+    /// Parse the JSON Lines format of version 2, skipping the header and empty lines.
+    fn parse_json_lines(contents: &str) -> RequestFile {
+        let mut file = RequestFile {
+            version: REQUEST_FILE_VERSION,
+            ..Default::default()
+        };
+
+        let lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .skip(1);
+        for (index, line) in lines {
+            let entry = serde_json::from_str::<RequestEntry>(line)
+                .map_err(|e| e.to_string())
+                .and_then(|entry| entry.to_inputs().map(|inputs| (entry, inputs)))
+                .and_then(|(entry, inputs)| check_inputs(&inputs).map(|_| (entry, inputs)));
+
+            match entry {
+                Ok((entry, inputs)) => file.requests.push(PlannedRequest {
+                    id: entry.id.unwrap_or_else(|| file.requests.len().to_string()),
+                    inputs,
+                    send_at: entry.send_at_ms.map(Duration::from_millis),
+                    deadline: entry.deadline_ms.map(Duration::from_millis),
+                    priority: entry.priority,
+                    expected: entry.expected,
+                }),
+                Err(e) => file.rejected.push((index + 1, e)),
+            }
+        }
+
+        file
+    }
+}
+
+/// Check the parameters of a request with a known function name: the number of values,
+/// and that the counts and populations are integers that fit in the `int32` fields the client sends.
+///
+/// Requests with an unknown function name are left to the client, which fails them when they are sent.
+fn check_inputs(inputs: &[String]) -> Result<(), String> {
+    let fields: &[&str] = match inputs[0].as_str() {
+        "getPopulationofCountry" => &["country"],
+        "getNumberofCities" => &["country", "min_population"],
+        "getNumberofCountries" => &["city_count", "min_population"],
+        "getNumberofCountriesMax" => &["city_count", "min_population", "max_population"],
+        _ => return Ok(()),
+    };
+
+    // The function name, the parameters and the zone
+    if inputs.len() != fields.len() + 2 {
+        return Err(format!(
+            "{} needs {} parameters and a zone, got: {}",
+            inputs[0],
+            fields.len(),
+            inputs[1..].join(" ")
+        ));
+    }
+
+    for (field, value) in fields.iter().zip(&inputs[1..]) {
+        if *field != "country" && value.parse::<i32>().is_err() {
+            return Err(format!("{} is not an int32: {}", field, value));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"{"format":"rs-distributed-stats/requests","version":2}"#;
+
+    #[test]
+    fn parses_the_text_format() {
+        let file = RequestFile::parse(
+            "getPopulationofCountry Norway Zone:1\ngetNumberofCountriesMax 2 1000 90000 Zone:3\n",
+        )
+        .unwrap();
+
+        assert_eq!(file.version, 1);
+        assert!(file.rejected.is_empty());
+        assert_eq!(file.requests.len(), 2);
+        assert_eq!(file.requests[1].id, "1");
+        assert_eq!(file.requests[1].function(), "getNumberofCountriesMax");
+        assert_eq!(
+            file.requests[1].inputs,
+            ["getNumberofCountriesMax", "2", "1000", "90000", "Zone:3"]
+        );
+    }
+
+    #[test]
+    fn rejects_text_lines_with_their_line_number() {
+        let file = RequestFile::parse(
+            "getPopulationofCountry Norway Zone:1\n\
+             getPopulationofCountry\n\
+             getNumberofCities Norway 3000000000 Zone:1\n\
+             getNumberofCountries 2 Zone:1\n\
+             getNumberofCities Norway many Zone:2\n",
+        )
+        .unwrap();
+
+        assert_eq!(file.requests.len(), 1);
+        let lines: Vec<usize> = file.rejected.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 3, 4, 5]);
+        assert!(file.rejected[1].1.contains("min_population"));
+        assert!(file.rejected[2].1.contains("needs 2 parameters"));
+    }
+
+    #[test]
+    fn leaves_unknown_text_functions_to_the_client() {
+        let file = RequestFile::parse("getEverything 1 2 Zone:1\n").unwrap();
+
+        assert_eq!(file.requests.len(), 1);
+        assert!(file.rejected.is_empty());
+    }
+
+    #[test]
+    fn parses_json_lines() {
+        let contents = format!(
+            "{}\n\n{}\n{}\n",
+            HEADER,
+            r#"{"id":"a1","rpc":"getNumberofCities","country":"Norway","min_population":5000,"zone":2,"send_at_ms":500,"deadline_ms":300,"priority":1,"expected":12}"#,
+            r#"{"rpc":"getPopulationofCountry","country":"Sweden","zone":1}"#
+        );
+        let file = RequestFile::parse(&contents).unwrap();
+
+        assert_eq!(file.version, REQUEST_FILE_VERSION);
+        assert!(file.rejected.is_empty());
+        let first = &file.requests[0];
+        assert_eq!(first.id, "a1");
+        assert_eq!(
+            first.inputs,
+            ["getNumberofCities", "Norway", "5000", "Zone:2"]
+        );
+        assert_eq!(first.send_at, Some(Duration::from_millis(500)));
+        assert_eq!(first.deadline, Some(Duration::from_millis(300)));
+        assert_eq!(first.priority, 1);
+        assert_eq!(first.expected, Some(12));
+        assert_eq!(file.requests[1].id, "1");
+    }
+
+    #[test]
+    fn rejects_json_lines_with_their_line_number() {
+        let contents = format!(
+            "{}\n{}\n{}\n{}\n{}\n",
+            HEADER,
+            r#"{"rpc":"getNumberofCities","country":"Norway","zone":1}"#,
+            r#"{"rpc":"getNumberofCities","country":"Norway","min_population":4294967296,"zone":1}"#,
+            r#"{"rpc":"getPopulationofCountry","country":"Norway","zone":1,"colour":"red"}"#,
+            r#"{"rpc":"getPopulationofCountry","country":"Norway","zone":1}"#
+        );
+        let file = RequestFile::parse(&contents).unwrap();
+
+        assert_eq!(file.requests.len(), 1);
+        let lines: Vec<usize> = file.rejected.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 3, 4]);
+        assert!(file.rejected[0].1.contains("min_population"));
+        assert!(file.rejected[1].1.contains("not an int32"));
+    }
+
+    #[test]
+    fn rejects_unknown_headers() {
+        assert!(RequestFile::parse(r#"{"format":"other","version":2}"#).is_err());
+        assert!(
+            RequestFile::parse(r#"{"format":"rs-distributed-stats/requests","version":9}"#)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::cache::{Cache, EvictionPolicy};
+
+/// Response held by the client cache, with the id of the server that produced it.
+#[derive(Debug, Clone, Copy)]
+struct CachedResponse {
+    server_id: u32,
+    value: i32,
+}
+
+/// Client side cache of responses, keyed by the request line.
+///
+/// Servers send a `cache_epoch` with each response. When the epoch of a server changes,
+/// every response cached from that server is invalidated.
+/// Epochs are counted by each server, so servers of the same zone can have different epochs.
+pub struct ResponseCache {
+    cache: Cache<String, CachedResponse>,
+    /// Last epoch seen from each server, by server id.
+    epochs: HashMap<u32, String>,
+}
+
+impl ResponseCache {
+    /// Create a cache holding at most `capacity` responses.
+    ///
+    /// With a TTL responses expire and the oldest is evicted when full, otherwise the least recently used is evicted.
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        let policy = match ttl {
+            Some(ttl) => EvictionPolicy::Ttl(ttl),
+            None => EvictionPolicy::Lru,
+        };
+
+        ResponseCache {
+            cache: Cache::new(capacity, policy),
+            epochs: HashMap::new(),
+        }
+    }
+
+    /// Get the cached result of a request.
+    pub fn get(&mut self, key: &str) -> Option<i32> {
+        self.cache.get(key).map(|response| response.value)
+    }
+
+    /// Cache the result of a request answered by the given server.
+    pub fn insert(&mut self, key: String, server_id: u32, value: i32) {
+        self.cache.insert(key, CachedResponse { server_id, value });
+    }
+
+    /// Remove every response cached from the given server.
+    pub fn invalidate_server(&mut self, server_id: u32) {
+        self.cache
+            .retain(|_, response| response.server_id != server_id);
+    }
+
+    /// Record the cache epoch sent by the given server.
+    ///
+    /// Invalidates the server and returns true if the epoch changed since its last response.
+    pub fn observe_epoch(&mut self, server_id: u32, epoch: &str) -> bool {
+        match self.epochs.insert(server_id, epoch.to_string()) {
+            Some(previous) if previous != epoch => {
+                self.invalidate_server(server_id);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn servers_with_different_epochs_do_not_invalidate_each_other() {
+        let mut cache = ResponseCache::new(10, None);
+        assert!(!cache.observe_epoch(1, "0"));
+        cache.insert(String::from("a"), 1, 10);
+        assert!(!cache.observe_epoch(2, "3"));
+        cache.insert(String::from("b"), 2, 20);
+
+        // Responses alternate between the two servers, each with its own epoch
+        for _ in 0..3 {
+            assert!(!cache.observe_epoch(1, "0"));
+            assert!(!cache.observe_epoch(2, "3"));
+        }
+        assert_eq!(cache.get("a"), Some(10));
+        assert_eq!(cache.get("b"), Some(20));
+    }
+
+    #[test]
+    fn new_epoch_invalidates_only_its_server() {
+        let mut cache = ResponseCache::new(10, None);
+        cache.observe_epoch(1, "0");
+        cache.observe_epoch(2, "0");
+        cache.insert(String::from("a"), 1, 10);
+        cache.insert(String::from("b"), 2, 20);
+
+        assert!(cache.observe_epoch(1, "1"));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(20));
+    }
+}
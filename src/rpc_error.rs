@@ -0,0 +1,334 @@
+use std::fmt;
+use std::time::Duration;
+
+use prost::Message;
+use tonic::codegen::Bytes;
+use tonic::{Code, Status};
+
+/// Messages of the `google.rpc` error model, generated from `proto/google/rpc`.
+pub mod google_rpc {
+    tonic::include_proto!("google.rpc");
+}
+
+use google_rpc::bad_request::FieldViolation;
+use google_rpc::{BadRequest, ErrorInfo, ResourceInfo, RetryInfo};
+
+/// Domain of the `ErrorInfo` reasons of the statistics service.
+pub const ERROR_DOMAIN: &str = "statservice";
+
+/// Prefix of the type URL of the error details packed in `google.protobuf.Any`.
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Error of a `StatMethods` call, sent to the client as a gRPC status with `google.rpc` error details.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatError {
+    /// Fields of the request are not valid, with the field name and the reason for each.
+    InvalidArgument(Vec<(String, String)>),
+    /// The requested country has no cities in the database.
+    CountryNotFound(String),
+    /// No database connection became free in time.
+    PoolExhausted { retry_after: Duration },
+    /// The server has too many requests in flight and does not accept more.
+    Overloaded { retry_after: Duration },
+    /// The result does not fit in the `int32` field of version 1 of the service, with the field name.
+    ResultOutOfRange(String),
+    /// The query failed on the database.
+    QueryFailed,
+}
+
+impl StatError {
+    /// Code of the gRPC status of the error.
+    pub fn code(&self) -> Code {
+        match self {
+            StatError::InvalidArgument(_) => Code::InvalidArgument,
+            StatError::CountryNotFound(_) => Code::NotFound,
+            StatError::PoolExhausted { .. } => Code::ResourceExhausted,
+            StatError::Overloaded { .. } => Code::Unavailable,
+            StatError::ResultOutOfRange(_) => Code::OutOfRange,
+            StatError::QueryFailed => Code::Internal,
+        }
+    }
+
+    /// Constant reason in the `ErrorInfo` detail of the error.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            StatError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            StatError::CountryNotFound(_) => "COUNTRY_NOT_FOUND",
+            StatError::PoolExhausted { .. } => "POOL_EXHAUSTED",
+            StatError::Overloaded { .. } => "SERVER_OVERLOADED",
+            StatError::ResultOutOfRange(_) => "RESULT_OUT_OF_RANGE",
+            StatError::QueryFailed => "QUERY_FAILED",
+        }
+    }
+}
+
+impl fmt::Display for StatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatError::InvalidArgument(violations) => {
+                let fields: Vec<String> = violations
+                    .iter()
+                    .map(|(field, description)| format!("{}: {}", field, description))
+                    .collect();
+                write!(f, "Invalid request ({})", fields.join(", "))
+            }
+            StatError::CountryNotFound(country) => write!(f, "Country not found: {}", country),
+            StatError::PoolExhausted { .. } => write!(f, "Server is overloaded"),
+            StatError::Overloaded { .. } => write!(f, "Server has too many requests in flight"),
+            StatError::ResultOutOfRange(field) => write!(
+                f,
+                "Result does not fit in {} (int32), use statservice.v2",
+                field
+            ),
+            StatError::QueryFailed => write!(f, "Internal server error"),
+        }
+    }
+}
+
+impl From<StatError> for Status {
+    /// Build the status with an `ErrorInfo` detail, and a `BadRequest`, `ResourceInfo` or `RetryInfo` detail depending on the error.
+    fn from(error: StatError) -> Status {
+        let mut details = vec![pack(
+            "google.rpc.ErrorInfo",
+            &ErrorInfo {
+                reason: error.reason().to_string(),
+                domain: ERROR_DOMAIN.to_string(),
+                metadata: Default::default(),
+            },
+        )];
+
+        match &error {
+            StatError::InvalidArgument(violations) => details.push(pack(
+                "google.rpc.BadRequest",
+                &BadRequest {
+                    field_violations: violations
+                        .iter()
+                        .map(|(field, description)| FieldViolation {
+                            field: field.clone(),
+                            description: description.clone(),
+                        })
+                        .collect(),
+                },
+            )),
+            StatError::CountryNotFound(country) => details.push(pack(
+                "google.rpc.ResourceInfo",
+                &ResourceInfo {
+                    resource_type: String::from("country"),
+                    resource_name: country.clone(),
+                    ..Default::default()
+                },
+            )),
+            StatError::PoolExhausted { retry_after } | StatError::Overloaded { retry_after } => {
+                details.push(pack(
+                    "google.rpc.RetryInfo",
+                    &RetryInfo {
+                        retry_delay: prost_types::Duration::try_from(*retry_after).ok(),
+                    },
+                ))
+            }
+            StatError::ResultOutOfRange(_) | StatError::QueryFailed => {}
+        }
+
+        let code = error.code();
+        let message = error.to_string();
+        let status = google_rpc::Status {
+            code: code as i32,
+            message: message.clone(),
+            details,
+        };
+        Status::with_details(code, message, Bytes::from(status.encode_to_vec()))
+    }
+}
+
+/// Pack an error detail in a `google.protobuf.Any` with the given full message name.
+fn pack(name: &str, message: &impl Message) -> prost_types::Any {
+    prost_types::Any {
+        type_url: format!("{}{}", TYPE_URL_PREFIX, name),
+        value: message.encode_to_vec(),
+    }
+}
+
+/// Error details read from the status of a failed call.
+///
+/// Every field is empty if the server did not send the detail, or the details could not be decoded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorDetails {
+    /// Reason from the `ErrorInfo` detail, e.g. `COUNTRY_NOT_FOUND`.
+    pub reason: Option<String>,
+    /// Field name and reason of each field violation in the `BadRequest` detail.
+    pub field_violations: Vec<(String, String)>,
+    /// Type and name of the resource in the `ResourceInfo` detail.
+    pub resource: Option<(String, String)>,
+    /// Delay from the `RetryInfo` detail.
+    pub retry_after: Option<Duration>,
+}
+
+impl ErrorDetails {
+    /// Decode the `google.rpc` error details of a status.
+    pub fn from_status(status: &Status) -> Self {
+        let mut details = ErrorDetails::default();
+        let Ok(rpc_status) = google_rpc::Status::decode(status.details()) else {
+            return details;
+        };
+
+        for any in rpc_status.details {
+            let name = any.type_url.rsplit('/').next().unwrap_or_default();
+            let value = any.value.as_slice();
+            match name {
+                "google.rpc.ErrorInfo" => {
+                    if let Ok(info) = ErrorInfo::decode(value) {
+                        details.reason = Some(info.reason);
+                    }
+                }
+                "google.rpc.BadRequest" => {
+                    if let Ok(bad_request) = BadRequest::decode(value) {
+                        details.field_violations = bad_request
+                            .field_violations
+                            .into_iter()
+                            .map(|violation| (violation.field, violation.description))
+                            .collect();
+                    }
+                }
+                "google.rpc.ResourceInfo" => {
+                    if let Ok(info) = ResourceInfo::decode(value) {
+                        details.resource = Some((info.resource_type, info.resource_name));
+                    }
+                }
+                "google.rpc.RetryInfo" => {
+                    details.retry_after = RetryInfo::decode(value)
+                        .ok()
+                        .and_then(|info| info.retry_delay)
+                        .and_then(|delay| Duration::try_from(delay).ok());
+                }
+                _ => {}
+            }
+        }
+
+        details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Convert the error into a status, and read the status back as a client would.
+    fn round_trip(error: StatError) -> (Status, ErrorDetails) {
+        let status = Status::from(error);
+        let details = ErrorDetails::from_status(&status);
+        (status, details)
+    }
+
+    #[test]
+    fn invalid_argument_has_field_violations() {
+        let violations = vec![
+            (
+                String::from("country_name"),
+                String::from("must not be empty"),
+            ),
+            (String::from("city_count"), String::from("must be positive")),
+        ];
+        let (status, details) = round_trip(StatError::InvalidArgument(violations.clone()));
+
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Invalid request (country_name: must not be empty, city_count: must be positive)"
+        );
+        assert_eq!(
+            details,
+            ErrorDetails {
+                reason: Some(String::from("INVALID_ARGUMENT")),
+                field_violations: violations,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn country_not_found_has_resource_info() {
+        let (status, details) = round_trip(StatError::CountryNotFound(String::from("Atlantis")));
+
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "Country not found: Atlantis");
+        assert_eq!(
+            details,
+            ErrorDetails {
+                reason: Some(String::from("COUNTRY_NOT_FOUND")),
+                resource: Some((String::from("country"), String::from("Atlantis"))),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn pool_exhausted_has_retry_info() {
+        let retry_after = Duration::from_millis(250);
+        let (status, details) = round_trip(StatError::PoolExhausted { retry_after });
+
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(
+            details,
+            ErrorDetails {
+                reason: Some(String::from("POOL_EXHAUSTED")),
+                retry_after: Some(retry_after),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn overloaded_has_retry_info() {
+        let retry_after = Duration::from_secs(1);
+        let (status, details) = round_trip(StatError::Overloaded { retry_after });
+
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(
+            details,
+            ErrorDetails {
+                reason: Some(String::from("SERVER_OVERLOADED")),
+                retry_after: Some(retry_after),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn result_out_of_range_has_only_the_reason() {
+        let (status, details) = round_trip(StatError::ResultOutOfRange(String::from("population")));
+
+        assert_eq!(status.code(), Code::OutOfRange);
+        assert_eq!(
+            status.message(),
+            "Result does not fit in population (int32), use statservice.v2"
+        );
+        assert_eq!(
+            details,
+            ErrorDetails {
+                reason: Some(String::from("RESULT_OUT_OF_RANGE")),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn query_failed_has_only_the_reason() {
+        let (status, details) = round_trip(StatError::QueryFailed);
+
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(
+            details,
+            ErrorDetails {
+                reason: Some(String::from("QUERY_FAILED")),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn status_without_details_has_empty_details() {
+        let status = Status::unavailable("Connection refused");
+
+        assert_eq!(ErrorDetails::from_status(&status), ErrorDetails::default());
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::topology::{Topology, DEFAULT_TOPOLOGY_PATH};
+
+/// A simulation run: the servers, proxy and clients to start, and how long each step may take.
+///
+/// Loaded from a TOML file by the `simulate` binary.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Name of the run, used in the name of the results directory.
+    pub name: String,
+    /// Run the servers and clients as processes or as tasks of the `simulate` process.
+    #[serde(default)]
+    pub mode: RunMode,
+    /// Path of the topology file given to every process.
+    #[serde(default = "default_topology")]
+    pub topology: String,
+    /// Directory the results directory of each run is created in.
+    #[serde(default = "default_results_dir")]
+    pub results_dir: String,
+    /// Time the servers and the proxy may take to report that they are serving, in seconds.
+    #[serde(default = "default_ready_timeout")]
+    pub ready_timeout_s: u64,
+    /// Time the clients may take to finish, in seconds. Clients still running are stopped.
+    #[serde(default = "default_run_timeout")]
+    pub run_timeout_s: u64,
+    /// Time a process may take to exit after it is asked to stop, in seconds. It is killed afterwards.
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout_s: u64,
+    /// Servers to start. Every server of the topology is started if none are given.
+    #[serde(default)]
+    pub servers: Vec<ServerProcess>,
+    /// Start the proxy on the address of the topology.
+    #[serde(default)]
+    pub proxy: bool,
+    pub clients: Vec<ClientProcess>,
+}
+
+/// How the servers and clients of a scenario are run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    /// Every server and client is a process, connected over TCP.
+    #[default]
+    Process,
+    /// Every server and client is a task of one tokio runtime, connected over an in-memory network.
+    InProcess,
+    /// Like `InProcess`, but on a single thread with a paused clock. Simulated delays advance
+    /// virtual time instead of waiting, so runs with the same seeds give the same results.
+    Deterministic,
+}
+
+impl RunMode {
+    /// Whether the servers and clients run as tasks of the `simulate` process.
+    pub fn is_in_process(self) -> bool {
+        matches!(self, RunMode::InProcess | RunMode::Deterministic)
+    }
+}
+
+/// A server of the topology and the extra flags it is started with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerProcess {
+    pub id: u32,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A client, the request file it sends and the extra flags it is started with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientProcess {
+    /// Zone of the client, which also names its log file.
+    pub zone: u32,
+    /// Path of the request file.
+    pub requests: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_topology() -> String {
+    String::from(DEFAULT_TOPOLOGY_PATH)
+}
+
+fn default_results_dir() -> String {
+    String::from("results")
+}
+
+fn default_ready_timeout() -> u64 {
+    10
+}
+
+fn default_run_timeout() -> u64 {
+    300
+}
+
+fn default_shutdown_timeout() -> u64 {
+    5
+}
+
+impl Scenario {
+    /// Load the scenario from a TOML file, and the topology it uses.
+    ///
+    /// The scenario is validated against the topology.
+    pub fn load(path: &str) -> Result<(Scenario, Topology), Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read scenario file {}: {}", path, e))?;
+
+        let mut scenario: Scenario = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse scenario file {}: {}", path, e))?;
+
+        let topology = Topology::load(&scenario.topology)?;
+        if scenario.servers.is_empty() {
+            scenario.servers = topology
+                .servers
+                .iter()
+                .map(|server| ServerProcess {
+                    id: server.id,
+                    args: Vec::new(),
+                })
+                .collect();
+        }
+
+        scenario.validate(&topology)?;
+        Ok((scenario, topology))
+    }
+
+    /// Check that the servers and client zones are in the topology, that no server or client zone is used twice,
+    /// that the name can be used in a directory name, and that a deterministic run has every seed it needs.
+    fn validate(&self, topology: &Topology) -> Result<(), Box<dyn Error>> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "Scenario name can only have letters, digits, '-' and '_': {}",
+                self.name
+            )
+            .into());
+        }
+
+        let mut server_ids = HashSet::new();
+        for server in &self.servers {
+            if !server_ids.insert(server.id) {
+                return Err(format!("Server {} is started more than once", server.id).into());
+            }
+            if topology.server(server.id).is_none() {
+                return Err(format!("Server {} is not defined in the topology", server.id).into());
+            }
+        }
+
+        if self.clients.is_empty() {
+            return Err("The scenario has no clients".into());
+        }
+
+        // Clients write their log to a file named after their zone
+        let mut client_zones = HashSet::new();
+        for client in &self.clients {
+            if !client_zones.insert(client.zone) {
+                return Err(format!("Zone {} has more than one client", client.zone).into());
+            }
+            if topology.zone(client.zone).is_none() {
+                return Err(
+                    format!("Client zone {} is not defined in the topology", client.zone).into(),
+                );
+            }
+        }
+
+        if self.proxy && topology.proxy.is_none() {
+            return Err(
+                "The scenario starts the proxy, but the topology has no proxy address".into(),
+            );
+        }
+
+        // The latency layers of the servers and the clients would each add the delays of a call
+        let server_latency = self
+            .servers
+            .iter()
+            .find(|server| server.args.iter().any(|arg| arg == "--latency"));
+        let client_latency = self
+            .clients
+            .iter()
+            .find(|client| !client.args.iter().any(|arg| arg == "--no-latency"));
+        if let (Some(server), Some(client)) = (server_latency, client_latency) {
+            return Err(format!(
+                "Server {} injects network latency with --latency, so the client in zone {} needs --no-latency",
+                server.id, client.zone
+            )
+            .into());
+        }
+
+        if self.proxy && self.mode.is_in_process() {
+            return Err("The proxy can only be started in process mode".into());
+        }
+
+        // Random seeds would give different delays and faults in each run
+        if self.mode == RunMode::Deterministic {
+            let unseeded = [
+                ("latency", topology.latency.seed.is_none()),
+                (
+                    "faults",
+                    topology.faults.is_enabled() && topology.faults.seed.is_none(),
+                ),
+                (
+                    "service_time",
+                    topology.service_time.is_enabled() && topology.service_time.seed.is_none(),
+                ),
+            ];
+            if let Some((section, _)) = unseeded.iter().find(|(_, unseeded)| *unseeded) {
+                return Err(format!(
+                    "Deterministic mode needs a seed in the [{}] section of the topology",
+                    section
+                )
+                .into());
+            }
+
+            let has_flag =
+                |client: &ClientProcess, flag: &str| client.args.iter().any(|arg| arg == flag);
+            for client in &self.clients {
+                if has_flag(client, "--open-loop") && !has_flag(client, "--arrival-seed") {
+                    return Err(format!(
+                        "Deterministic mode needs an --arrival-seed for the open-loop client in zone {}",
+                        client.zone
+                    )
+                    .into());
+                }
+            }
+        }
+
+        if self.ready_timeout_s == 0 || self.run_timeout_s == 0 {
+            return Err("The ready and run timeouts must be at least one second".into());
+        }
+
+        Ok(())
+    }
+
+    pub fn ready_timeout(&self) -> Duration {
+        Duration::from_secs(self.ready_timeout_s)
+    }
+
+    pub fn run_timeout(&self) -> Duration {
+        Duration::from_secs(self.run_timeout_s)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_s)
+    }
+}
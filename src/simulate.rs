@@ -0,0 +1,723 @@
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rs_distributed_stats::client_log::client_log_path;
+use rs_distributed_stats::memory_transport::MemoryNetwork;
+use rs_distributed_stats::scenario::{RunMode, Scenario};
+use rs_distributed_stats::shutdown::shutdown_signal;
+use rs_distributed_stats::topology::Topology;
+use rs_distributed_stats::{stat_client, stat_server};
+use serde::Serialize;
+use tokio::process::{Child, Command};
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tonic::transport::Endpoint;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+/// Name of the statistics service in the health service of the servers and the proxy.
+const STAT_SERVICE_NAME: &str = "statservice.StatMethods";
+
+/// Time between two health checks of a server that is not ready yet.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Outcome of a server, proxy or client, written to `summary.json`.
+#[derive(Serialize)]
+struct ProcessSummary {
+    name: String,
+    /// Exit code, not set if the process was ended by a signal or ran in-process.
+    exit_code: Option<i32>,
+    success: bool,
+    stopped: bool,
+    duration_ms: u128,
+    /// Error returned by a server or client run in-process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Outcome of a run, written to `summary.json`.
+#[derive(Serialize)]
+struct RunSummary {
+    scenario: String,
+    mode: RunMode,
+    started_unix_s: u64,
+    duration_ms: u128,
+    /// Whether every server and the proxy reported that they were serving.
+    ready: bool,
+    /// Whether the clients finished before the run timeout.
+    finished: bool,
+    interrupted: bool,
+    processes: Vec<ProcessSummary>,
+}
+
+/// Outcome of the servers, proxy and clients of a run, in either mode.
+#[derive(Default)]
+struct RunOutcome {
+    ready: bool,
+    finished: bool,
+    interrupted: bool,
+    processes: Vec<ProcessSummary>,
+}
+
+impl RunOutcome {
+    /// Names of the clients that did not succeed.
+    fn failed_clients(&self) -> Vec<&str> {
+        self.processes
+            .iter()
+            .filter(|process| process.name.starts_with("client_") && !process.success)
+            .map(|process| process.name.as_str())
+            .collect()
+    }
+}
+
+/// A server, proxy or client started as a process by the simulation.
+struct Process {
+    /// Name of the process in the results directory, e.g. `server_1`.
+    name: String,
+    child: Child,
+    started: Instant,
+    /// Exit status and run time, once the process has exited.
+    exit: Option<(ExitStatus, Duration)>,
+    /// Whether the process was asked to stop by the simulation, instead of exiting by itself.
+    stopped: bool,
+}
+
+impl Process {
+    /// Start a binary from `bin_dir`, writing its output to `<name>.log` in the results directory.
+    fn spawn(
+        bin_dir: &Path,
+        binary: &str,
+        name: String,
+        args: &[String],
+        results_dir: &Path,
+    ) -> Result<Process, Box<dyn Error>> {
+        let log_path = results_dir.join(format!("{}.log", name));
+        let log = File::create(&log_path)
+            .map_err(|e| format!("Failed to create log file {}: {}", log_path.display(), e))?;
+
+        let program = bin_dir.join(format!("{}{}", binary, env::consts::EXE_SUFFIX));
+        let child = Command::new(&program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", program.display(), e))?;
+
+        println!(
+            "[INFO] Started {} (pid {})",
+            name,
+            child.id().unwrap_or_default()
+        );
+
+        Ok(Process {
+            name,
+            child,
+            started: Instant::now(),
+            exit: None,
+            stopped: false,
+        })
+    }
+
+    /// Record the exit status if the process has exited.
+    fn poll_exit(&mut self) -> Result<Option<ExitStatus>, Box<dyn Error>> {
+        if self.exit.is_none() {
+            if let Some(status) = self.child.try_wait()? {
+                self.exit = Some((status, self.started.elapsed()));
+            }
+        }
+        Ok(self.exit.map(|(status, _)| status))
+    }
+
+    /// Wait for the process to exit by itself.
+    async fn wait(&mut self) {
+        if self.exit.is_some() {
+            return;
+        }
+        match self.child.wait().await {
+            Ok(status) => {
+                println!("[INFO] {} finished with {}", self.name, status);
+                self.exit = Some((status, self.started.elapsed()));
+            }
+            Err(e) => println!("[ERROR] Failed to wait for {}: {}", self.name, e),
+        }
+    }
+
+    /// Wait until the process reports the statistics service as serving on the given address.
+    ///
+    /// Fails if the process exits or is not serving before the deadline.
+    async fn wait_ready(
+        &mut self,
+        addr: &str,
+        deadline: Instant,
+    ) -> Result<Duration, Box<dyn Error>> {
+        loop {
+            if let Some(status) = self.poll_exit()? {
+                return Err(
+                    format!("{} exited with {} before it was ready", self.name, status).into(),
+                );
+            }
+
+            if is_serving(addr, None).await {
+                return Ok(self.started.elapsed());
+            }
+
+            if Instant::now() + HEALTH_CHECK_INTERVAL >= deadline {
+                return Err(format!("{} was not ready in time", self.name).into());
+            }
+            tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Ask the process to stop, and kill it if it is still running after the timeout.
+    async fn stop(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        if self.poll_exit()?.is_some() {
+            return Ok(());
+        }
+        self.stopped = true;
+
+        // SIGTERM lets the servers and the proxy finish the requests in flight
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            // SAFETY: sending a signal to a child process of this process
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+        }
+        #[cfg(not(unix))]
+        self.child.start_kill()?;
+
+        match tokio::time::timeout(timeout, self.child.wait()).await {
+            Ok(status) => self.exit = Some((status?, self.started.elapsed())),
+            Err(_) => {
+                println!(
+                    "[WARN] {} did not stop within {} s, killing it",
+                    self.name,
+                    timeout.as_secs()
+                );
+                self.child.kill().await?;
+                self.exit = Some((self.child.wait().await?, self.started.elapsed()));
+            }
+        }
+        Ok(())
+    }
+
+    fn summary(&self) -> ProcessSummary {
+        let (exit_code, success, duration) = match self.exit {
+            Some((status, duration)) => (status.code(), status.success(), duration),
+            None => (None, false, self.started.elapsed()),
+        };
+        ProcessSummary {
+            name: self.name.clone(),
+            exit_code,
+            success,
+            stopped: self.stopped,
+            duration_ms: duration.as_millis(),
+            error: None,
+        }
+    }
+}
+
+/// A server or client running as a task of the simulation, in in-process mode.
+struct Task {
+    /// Name of the task in the summary, e.g. `server_1`.
+    name: String,
+    handle: JoinHandle<Result<(), String>>,
+    started: Instant,
+    /// Result and run time, once the task has finished.
+    exit: Option<(Result<(), String>, Duration)>,
+    /// Whether the task was stopped by the simulation, instead of finishing by itself.
+    stopped: bool,
+}
+
+impl Task {
+    fn spawn(
+        name: String,
+        future: impl Future<Output = Result<(), String>> + Send + 'static,
+    ) -> Task {
+        Task {
+            name,
+            handle: tokio::spawn(future),
+            started: Instant::now(),
+            exit: None,
+            stopped: false,
+        }
+    }
+
+    /// Wait for the task to finish by itself.
+    async fn wait(&mut self) {
+        if self.exit.is_some() {
+            return;
+        }
+        let result = match (&mut self.handle).await {
+            Ok(result) => result,
+            Err(e) => Err(e.to_string()),
+        };
+        match &result {
+            Ok(()) => println!("[INFO] {} finished", self.name),
+            Err(e) => println!("[ERROR] {} failed: {}", self.name, e),
+        }
+        self.exit = Some((result, self.started.elapsed()));
+    }
+
+    /// Wait until the server reports the statistics service as serving on the in-memory network.
+    ///
+    /// Fails if the server task ends or is not serving before the deadline.
+    async fn wait_ready(
+        &mut self,
+        addr: &str,
+        network: &Arc<MemoryNetwork>,
+        deadline: Instant,
+    ) -> Result<Duration, Box<dyn Error>> {
+        loop {
+            if self.handle.is_finished() {
+                self.wait().await;
+                return Err(format!("{} ended before it was ready", self.name).into());
+            }
+
+            if is_serving(addr, Some(network)).await {
+                return Ok(self.started.elapsed());
+            }
+
+            if Instant::now() + HEALTH_CHECK_INTERVAL >= deadline {
+                return Err(format!("{} was not ready in time", self.name).into());
+            }
+            tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Wait for a task that was asked to stop, and abort it if it is still running after the timeout.
+    async fn stop(&mut self, timeout: Duration) {
+        if self.exit.is_some() {
+            return;
+        }
+        self.stopped = true;
+
+        if tokio::time::timeout(timeout, self.wait()).await.is_err() {
+            self.handle.abort();
+            self.exit = Some((
+                Err(String::from("Aborted by the simulation")),
+                self.started.elapsed(),
+            ));
+        }
+    }
+
+    fn summary(&self) -> ProcessSummary {
+        let (error, duration) = match &self.exit {
+            Some((result, duration)) => (result.clone().err(), *duration),
+            None => (Some(String::from("Still running")), self.started.elapsed()),
+        };
+        ProcessSummary {
+            name: self.name.clone(),
+            exit_code: None,
+            success: error.is_none(),
+            stopped: self.stopped,
+            duration_ms: duration.as_millis(),
+            error,
+        }
+    }
+}
+
+/// Check the health service on the given address once, over TCP or the in-memory network.
+async fn is_serving(addr: &str, network: Option<&Arc<MemoryNetwork>>) -> bool {
+    let uri = format!("http://{}", addr);
+    let check = async {
+        let channel = match network {
+            Some(network) => network.channel(uri).await.ok()?,
+            None => Endpoint::from_shared(uri).ok()?.connect().await.ok()?,
+        };
+        let request = HealthCheckRequest {
+            service: STAT_SERVICE_NAME.to_string(),
+        };
+        HealthClient::new(channel).check(request).await.ok()
+    };
+
+    match tokio::time::timeout(HEALTH_CHECK_INTERVAL * 10, check).await {
+        Ok(Some(response)) => response.into_inner().status() == ServingStatus::Serving,
+        _ => false,
+    }
+}
+
+/// Wait for the clients to finish, until the run timeout of the scenario or Ctrl+C.
+///
+/// Returns whether the clients finished and whether the run was interrupted.
+async fn wait_for_clients(scenario: &Scenario, clients: impl Future<Output = ()>) -> (bool, bool) {
+    tokio::select! {
+        result = tokio::time::timeout(scenario.run_timeout(), clients) => {
+            if result.is_err() {
+                println!(
+                    "[WARN] Clients did not finish within {} s, stopping them",
+                    scenario.run_timeout_s
+                );
+            }
+            (result.is_ok(), false)
+        }
+        _ = shutdown_signal() => {
+            println!("[WARN] Interrupted, stopping the simulation");
+            (false, true)
+        }
+    }
+}
+
+/// Flags given to every server and client: the topology of the scenario.
+fn topology_args(scenario: &Scenario) -> Vec<String> {
+    vec![String::from("--topology"), scenario.topology.clone()]
+}
+
+/// Run the servers, the proxy and the clients as processes, writing their output to the results directory.
+async fn run_processes(
+    scenario: &Scenario,
+    topology: &Topology,
+    bin_dir: &Path,
+    results_dir: &Path,
+) -> Result<RunOutcome, Box<dyn Error>> {
+    let mut outcome = RunOutcome {
+        ready: true,
+        ..Default::default()
+    };
+
+    // Start the servers and the proxy, and wait until every one of them is serving
+    let mut services: Vec<(Process, String)> = Vec::new();
+    let ready_deadline = Instant::now() + scenario.ready_timeout();
+    for server in &scenario.servers {
+        let mut server_args = vec![server.id.to_string()];
+        server_args.extend(topology_args(scenario));
+        server_args.extend(server.args.iter().cloned());
+        let name = format!("server_{}", server.id);
+        let process = Process::spawn(bin_dir, "server", name, &server_args, results_dir)?;
+        let addr = topology.server(server.id).unwrap().addr.clone();
+        services.push((process, addr));
+    }
+    if let Some(addr) = topology.proxy.clone().filter(|_| scenario.proxy) {
+        let proxy_args = [scenario.topology.clone()];
+        let process = Process::spawn(
+            bin_dir,
+            "proxy",
+            String::from("proxy"),
+            &proxy_args,
+            results_dir,
+        )?;
+        services.push((process, addr));
+    }
+
+    for (service, addr) in services.iter_mut() {
+        match service.wait_ready(addr, ready_deadline).await {
+            Ok(elapsed) => println!(
+                "[INFO] {} is ready on {} after {} ms",
+                service.name,
+                addr,
+                elapsed.as_millis()
+            ),
+            Err(e) => {
+                println!("[ERROR] {}, see {}.log", e, service.name);
+                outcome.ready = false;
+                break;
+            }
+        }
+    }
+
+    // Start the clients and wait for them to finish
+    let mut clients: Vec<Process> = Vec::new();
+    if outcome.ready {
+        for client in &scenario.clients {
+            let mut client_args = vec![client.requests.clone(), client.zone.to_string()];
+            client_args.extend(topology_args(scenario));
+            client_args.extend(client.args.iter().cloned());
+            let name = format!("client_{}", client.zone);
+            clients.push(Process::spawn(
+                bin_dir,
+                "client",
+                name,
+                &client_args,
+                results_dir,
+            )?);
+        }
+
+        let run = async {
+            for client in clients.iter_mut() {
+                client.wait().await;
+            }
+        };
+        (outcome.finished, outcome.interrupted) = wait_for_clients(scenario, run).await;
+    }
+
+    // Stop the clients still running, then the proxy and the servers
+    let processes = clients
+        .iter_mut()
+        .chain(services.iter_mut().rev().map(|(service, _)| service));
+    for process in processes {
+        if let Err(e) = process.stop(scenario.shutdown_timeout()).await {
+            println!("[ERROR] Failed to stop {}: {}", process.name, e);
+        }
+    }
+    println!("[INFO] Every process has stopped");
+
+    outcome.processes = services
+        .iter()
+        .map(|(service, _)| service)
+        .chain(clients.iter())
+        .map(Process::summary)
+        .collect();
+    Ok(outcome)
+}
+
+/// Run the servers and the clients as tasks of this process, connected by an in-memory network.
+///
+/// The output of every server and client is printed by this process.
+/// In deterministic mode the servers run their queries inline, as blocking threads would let real time pass.
+async fn run_in_process(
+    scenario: &Scenario,
+    topology: &Topology,
+) -> Result<RunOutcome, Box<dyn Error>> {
+    let mut outcome = RunOutcome {
+        ready: true,
+        ..Default::default()
+    };
+    let network = Arc::new(MemoryNetwork::new());
+
+    // Start the servers, which stop when the shutdown flag is set
+    let (shutdown, shutdown_flag) = watch::channel(false);
+    let mut servers: Vec<(Task, String)> = Vec::new();
+    let ready_deadline = Instant::now() + scenario.ready_timeout();
+    for entry in &scenario.servers {
+        let mut server_args = topology_args(scenario);
+        if scenario.mode == RunMode::Deterministic {
+            server_args.push(String::from("--inline-queries"));
+        }
+        server_args.extend(entry.args.iter().cloned());
+        let options = stat_server::parse_options(&server_args)?;
+
+        let server_id = entry.id;
+        let network = network.clone();
+        let mut shutdown_flag = shutdown_flag.clone();
+        let task = Task::spawn(format!("server_{}", server_id), async move {
+            let stopped = async move {
+                let _ = shutdown_flag.wait_for(|stop| *stop).await;
+            };
+            stat_server::run_server(server_id, options, Some(network), stopped)
+                .await
+                .map_err(|e| e.to_string())
+        });
+        let addr = topology.server(server_id).unwrap().addr.clone();
+        servers.push((task, addr));
+    }
+
+    for (server, addr) in servers.iter_mut() {
+        match server.wait_ready(addr, &network, ready_deadline).await {
+            Ok(elapsed) => println!(
+                "[INFO] {} is ready on {} (in memory) after {} ms",
+                server.name,
+                addr,
+                elapsed.as_millis()
+            ),
+            Err(e) => {
+                println!("[ERROR] {}", e);
+                outcome.ready = false;
+                break;
+            }
+        }
+    }
+
+    // Start the clients and wait for them to finish
+    let mut clients: Vec<Task> = Vec::new();
+    if outcome.ready {
+        for entry in &scenario.clients {
+            let mut client_args = topology_args(scenario);
+            client_args.extend(entry.args.iter().cloned());
+            let options = stat_client::parse_options(&client_args)?.with_network(network.clone());
+
+            let file_path = entry.requests.clone();
+            let client_zone = entry.zone as i32;
+            let options = Arc::new(options);
+            clients.push(Task::spawn(format!("client_{}", entry.zone), async move {
+                stat_client::run_client(&file_path, client_zone, options)
+                    .await
+                    .map_err(|e| e.to_string())
+            }));
+        }
+
+        let run = async {
+            for client in clients.iter_mut() {
+                client.wait().await;
+            }
+        };
+        (outcome.finished, outcome.interrupted) = wait_for_clients(scenario, run).await;
+    }
+
+    // Abort the clients still running, then stop the servers
+    for client in clients.iter_mut() {
+        client.stop(Duration::ZERO).await;
+    }
+    let _ = shutdown.send(true);
+    for (server, _) in servers.iter_mut() {
+        server.stop(scenario.shutdown_timeout()).await;
+    }
+    println!("[INFO] Every server and client has stopped");
+
+    outcome.processes = servers
+        .iter()
+        .map(|(server, _)| server)
+        .chain(clients.iter())
+        .map(Task::summary)
+        .collect();
+    Ok(outcome)
+}
+
+/// Create a new results directory for the run, named after the scenario and the start time.
+fn create_results_dir(scenario: &Scenario, started_unix_s: u64) -> Result<PathBuf, Box<dyn Error>> {
+    let base = Path::new(&scenario.results_dir);
+    let mut dir = base.join(format!("{}-{}", scenario.name, started_unix_s));
+    let mut attempt = 1;
+    while dir.exists() {
+        attempt += 1;
+        dir = base.join(format!("{}-{}-{}", scenario.name, started_unix_s, attempt));
+    }
+    fs::create_dir_all(&dir).map_err(|e| {
+        format!(
+            "Failed to create results directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    Ok(dir)
+}
+
+/// Parse the optional simulation flags.
+///
+/// `--bin-dir <dir>` starts the server, proxy and client binaries from the given directory
+/// instead of the directory of the `simulate` binary.
+fn parse_options(flags: &[String]) -> Result<PathBuf, Box<dyn Error>> {
+    let mut bin_dir: Option<PathBuf> = None;
+
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", flag))?;
+
+        match flag.as_str() {
+            "--bin-dir" => bin_dir = Some(PathBuf::from(value)),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    match bin_dir {
+        Some(dir) => Ok(dir),
+        None => {
+            let exe = env::current_exe()?;
+            let dir = exe
+                .parent()
+                .ok_or("Could not find the directory of the simulate binary")?;
+            Ok(dir.to_path_buf())
+        }
+    }
+}
+
+/// Build the runtime of the run.
+///
+/// Deterministic runs use a single thread with a paused clock, which jumps to the next timer
+/// whenever every task is waiting, so simulated delays take no real time.
+fn build_runtime(mode: RunMode) -> std::io::Result<Runtime> {
+    match mode {
+        RunMode::Deterministic => Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build(),
+        RunMode::Process | RunMode::InProcess => Builder::new_multi_thread().enable_all().build(),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Parse the command-line arguments
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <Scenario file> [--bin-dir <dir>]", args[0]);
+        return Ok(());
+    }
+    let scenario_path = &args[1];
+    let bin_dir = parse_options(&args[2..])?;
+
+    let (scenario, topology) = Scenario::load(scenario_path)?;
+    let runtime = build_runtime(scenario.mode)?;
+    runtime.block_on(run(scenario_path, scenario, topology, bin_dir))
+}
+
+/// Run the scenario and write its results.
+async fn run(
+    scenario_path: &str,
+    scenario: Scenario,
+    topology: Topology,
+    bin_dir: PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let run_start = Instant::now();
+    let started_unix_s = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let results_dir = create_results_dir(&scenario, started_unix_s)?;
+    fs::copy(scenario_path, results_dir.join("scenario.toml"))?;
+    fs::copy(&scenario.topology, results_dir.join("topology.toml"))?;
+    println!(
+        "[INFO] Running scenario {} ({:?} mode) with results in {}",
+        scenario.name,
+        scenario.mode,
+        results_dir.display()
+    );
+
+    let outcome = match scenario.mode {
+        RunMode::Process => run_processes(&scenario, &topology, &bin_dir, &results_dir).await?,
+        RunMode::InProcess | RunMode::Deterministic => run_in_process(&scenario, &topology).await?,
+    };
+
+    // Collect the logs the clients wrote
+    for client in &scenario.clients {
+        let source = client_log_path(client.zone as i32);
+        let file_name = source.file_name().unwrap();
+        if let Err(e) = fs::copy(&source, results_dir.join(file_name)) {
+            println!(
+                "[WARN] Could not collect the log {}: {}",
+                source.display(),
+                e
+            );
+        }
+    }
+
+    let failed = outcome.failed_clients().join(", ");
+    let ready = outcome.ready;
+    let summary = RunSummary {
+        scenario: scenario.name.clone(),
+        mode: scenario.mode,
+        started_unix_s,
+        duration_ms: run_start.elapsed().as_millis(),
+        ready: outcome.ready,
+        finished: outcome.finished,
+        interrupted: outcome.interrupted,
+        processes: outcome.processes,
+    };
+    fs::write(
+        results_dir.join("summary.json"),
+        serde_json::to_string_pretty(&summary)?,
+    )?;
+
+    println!(
+        "[INFO] Scenario {} took {} ms, results written to {}",
+        scenario.name,
+        summary.duration_ms,
+        results_dir.display()
+    );
+
+    if !ready {
+        return Err("The servers were not ready, no clients were started".into());
+    }
+    if !failed.is_empty() {
+        return Err(format!("Clients did not succeed: {}", failed).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,1363 @@
+//! Client sending the requests of a request file, run by the `client` binary and by `simulate`.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{fs::File, io::Read};
+
+use crate::arrivals::{ArrivalProfile, ArrivalSchedule};
+use crate::client_log::{ClientLogRecord, ClientLogWriter, CLIENT_LOG_VERSION};
+use crate::fault_layer::{FaultLayer, FaultService};
+use crate::faults::FaultModel;
+use crate::latency::LatencyModel;
+use crate::latency_layer::{
+    LatencyLayer, LatencyService, NETWORK_DELAY_HEADER, REQUEST_KEY_HEADER,
+};
+use crate::memory_transport::MemoryNetwork;
+use crate::proto::stat_service::stat_methods_client::StatMethodsClient;
+use crate::proto::stat_service::{
+    NumberOfCitiesRequest, NumberOfCitiesResponse, NumberOfCountriesMaxRequest,
+    NumberOfCountriesMaxResponse, NumberOfCountriesRequest, NumberOfCountriesResponse,
+    PopulationRequest, PopulationResponse,
+};
+use crate::request_file::{PlannedRequest, RequestFile};
+use crate::response_cache::ResponseCache;
+use crate::rpc_error::ErrorDetails;
+use crate::stats::Summary;
+use crate::topology::{parse_zone, Topology, DEFAULT_TOPOLOGY_PATH};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status};
+use tower::util::Either;
+use tower::ServiceBuilder;
+
+/// Create a connection to given server and sends request.
+///
+/// Sends a gRPC request for getting the population of a given country.
+/// Needs the zone number of the client, index of the request in the file, intended send time (open-loop mode), vec of inputs and the client options.
+/// Without a proxy the request is sent directly to a server in the requested zone.
+/// Repeated requests are answered from the client cache when it is enabled.
+/// Should be used in a thread. Does not crash or panic the program.
+async fn create_client_and_get_population_of_country(
+    client_zone: i32,
+    request_index: u64,
+    send_time: Option<Instant>,
+    inputs: Vec<String>,
+    options: Arc<ClientOptions>,
+) -> Result<RequestTiming, Status> {
+    // Get variables from the line
+    assert!(inputs.len() == 3);
+    let country_name = inputs[1].clone();
+    let zone = match parse_zone(&inputs[2]) {
+        Some(val) => val,
+        None => {
+            println!("[ERROR] Failed to parse zone: {}", inputs[2]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    // Answer the request locally if the response is cached
+    let cache_key = inputs.join(" ");
+    if let Some(population) = get_cached_response(&options, &cache_key) {
+        println!(
+            "[INFO] getPopulationofCountry {} {}, Population {}, (served from client cache)",
+            country_name, zone, population
+        );
+        return Ok(RequestTiming::cached(population.into()));
+    }
+
+    // Connect to server:
+    let (server_addr, server_id) = match server_address(&options, zone) {
+        Some(server) => server,
+        None => {
+            println!("[ERROR] No server in zone {}", zone);
+            return Err(Status::invalid_argument("No server in the requested zone"));
+        }
+    };
+    let mut client = match connect(&options, server_addr, client_zone as u32, zone).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[ERROR] Failed to connect to server: {}", e);
+            return Err(Status::internal("Failed to connect to server"));
+        }
+    };
+
+    // Build the request to the server
+    let mut request = Request::new(PopulationRequest {
+        country: country_name.to_string(),
+    });
+
+    // Set zone data as meta data in the request
+    request
+        .metadata_mut()
+        .insert("client_zone", MetadataValue::from(client_zone));
+
+    request
+        .metadata_mut()
+        .insert("request_zone", MetadataValue::from(zone));
+
+    // Key for the simulated network latency, so the request gets the same delay in every run
+    request
+        .metadata_mut()
+        .insert(REQUEST_KEY_HEADER, MetadataValue::from(request_index));
+
+    // Capture the start time, or use the intended send time in open-loop mode
+    let start = send_time.unwrap_or_else(Instant::now);
+
+    // Get the response
+    let response: Response<PopulationResponse> =
+        match client.get_population_of_country(request).await {
+            Ok(response) => response,
+            Err(status) => {
+                log_failure(zone, &status);
+                return Err(status);
+            }
+        };
+
+    // Calculate turn around, execution and wait time
+    let turnaround_time = start.elapsed();
+    let execution_ms = response
+        .metadata()
+        .get("execution")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let network_ms = network_delay(response.metadata());
+    let waiting_ms: u64 =
+        (turnaround_time.as_millis() as u64).saturating_sub(execution_ms + network_ms);
+    let cache_status = cache_status(response.metadata());
+    let server = ServerInfo::from_metadata(response.metadata(), server_id);
+
+    let population: i32 = response.get_ref().population;
+
+    // Cache the response for repeated requests
+    cache_response(
+        &options,
+        cache_key,
+        server.id,
+        response.metadata(),
+        population,
+    );
+
+    // Print the result
+    println!("[INFO] getPopulationofCountry {} {}, Population {}, (turnaround time: {} ms, execution time:
+{} ms, waiting time: {} ms, network time: {} ms, processed by {})", country_name, zone, population, turnaround_time.as_millis(), execution_ms, waiting_ms, network_ms, server);
+
+    Ok(RequestTiming {
+        turnaround_ms: turnaround_time.as_millis() as u64,
+        execution_ms,
+        waiting_ms,
+        network_ms,
+        value: population.into(),
+        cache_status,
+        server,
+        cached: false,
+    })
+}
+
+/// Create a connection to given server and sends request.
+///
+/// Sends a gRPC request for getting the number of cities within a country where each city has at least the given amount of population.
+/// Needs the zone number of the client, index of the request in the file, intended send time (open-loop mode), vec of inputs and the client options.
+/// Without a proxy the request is sent directly to a server in the requested zone.
+/// Repeated requests are answered from the client cache when it is enabled.
+/// Should be used in a thread. Does not crash or panic the program.
+async fn create_client_and_get_number_of_cities(
+    client_zone: i32,
+    request_index: u64,
+    send_time: Option<Instant>,
+    inputs: Vec<String>,
+    options: Arc<ClientOptions>,
+) -> Result<RequestTiming, Status> {
+    // Get variables
+    assert!(inputs.len() == 4);
+    let country_name = inputs[1].clone();
+    let min = match inputs[2].parse::<i32>() {
+        Ok(val) => val,
+        Err(_) => {
+            println!("[ERROR] Failed to parse min variable: {}", inputs[2]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    // The zone of the request
+    let zone = match parse_zone(&inputs[3]) {
+        Some(val) => val,
+        None => {
+            println!("[ERROR] Failed to parse zone: {}", inputs[3]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    // Answer the request locally if the response is cached
+    let cache_key = inputs.join(" ");
+    if let Some(number_of_cities) = get_cached_response(&options, &cache_key) {
+        println!(
+            "[INFO] getNumberofCities for {} min: {}, Number of cities: {}, (served from client cache)",
+            country_name, min, number_of_cities
+        );
+        return Ok(RequestTiming::cached(number_of_cities.into()));
+    }
+
+    // Connect to server
+    let (server_addr, server_id) = match server_address(&options, zone) {
+        Some(server) => server,
+        None => {
+            println!("[ERROR] No server in zone {}", zone);
+            return Err(Status::invalid_argument("No server in the requested zone"));
+        }
+    };
+    let mut client = match connect(&options, server_addr, client_zone as u32, zone).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[ERROR] Failed to connect to server: {}", e);
+            return Err(Status::internal("Failed to connect to server"));
+        }
+    };
+
+    // Build the request to the server
+    let mut request = Request::new(NumberOfCitiesRequest {
+        country: country_name.to_string(),
+        min,
+    });
+
+    // Set zone data as meta data in the request
+    request
+        .metadata_mut()
+        .insert("client_zone", MetadataValue::from(client_zone));
+
+    request
+        .metadata_mut()
+        .insert("request_zone", MetadataValue::from(zone));
+
+    // Key for the simulated network latency, so the request gets the same delay in every run
+    request
+        .metadata_mut()
+        .insert(REQUEST_KEY_HEADER, MetadataValue::from(request_index));
+
+    // Capture the start time, or use the intended send time in open-loop mode
+    let start = send_time.unwrap_or_else(Instant::now);
+
+    // Get the response
+    let response: Response<NumberOfCitiesResponse> =
+        match client.get_number_of_cities(request).await {
+            Ok(response) => response,
+            Err(status) => {
+                log_failure(zone, &status);
+                return Err(status);
+            }
+        };
+
+    // Calculate turn around, execution and wait time
+    let turnaround_time = start.elapsed();
+    let execution_ms = response
+        .metadata()
+        .get("execution")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let network_ms = network_delay(response.metadata());
+    let waiting_ms: u64 =
+        (turnaround_time.as_millis() as u64).saturating_sub(execution_ms + network_ms);
+    let cache_status = cache_status(response.metadata());
+    let server = ServerInfo::from_metadata(response.metadata(), server_id);
+
+    let number_of_cities: i32 = response.get_ref().number_of_cities;
+
+    // Cache the response for repeated requests
+    cache_response(
+        &options,
+        cache_key,
+        server.id,
+        response.metadata(),
+        number_of_cities,
+    );
+
+    // Write to the clients log.
+
+    // Print the result
+    println!("[INFO] getNumberofCities for {} min: {}, Number of cities: {}, (turnaround time: {} ms, execution time:
+{} ms, waiting time: {} ms, network time: {} ms, processed by {})", country_name, min, number_of_cities, turnaround_time.as_millis(), execution_ms, waiting_ms, network_ms, server);
+
+    Ok(RequestTiming {
+        turnaround_ms: turnaround_time.as_millis() as u64,
+        execution_ms,
+        waiting_ms,
+        network_ms,
+        value: number_of_cities.into(),
+        cache_status,
+        server,
+        cached: false,
+    })
+}
+
+/// Create a connection to given server and sends request.
+///
+/// Sends a gRPC request for getting the number of countries that has the given amount of cities where each city has a given minimum population.
+/// Needs the zone number of the client, index of the request in the file, intended send time (open-loop mode), vec of inputs and the client options.
+/// Without a proxy the request is sent directly to a server in the requested zone.
+/// Repeated requests are answered from the client cache when it is enabled.
+/// Should be used in a thread. Does not crash or panic the program.
+async fn create_client_and_get_number_of_countries(
+    client_zone: i32,
+    request_index: u64,
+    send_time: Option<Instant>,
+    inputs: Vec<String>,
+    options: Arc<ClientOptions>,
+) -> Result<RequestTiming, Status> {
+    // Get variables
+    assert!(inputs.len() == 4);
+    let citycount = match inputs[1].parse::<i32>() {
+        Ok(val) => val,
+        Err(_) => {
+            println!("[ERROR] Failed to parse min variable: {}", inputs[1]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+    let min = match inputs[2].parse::<i32>() {
+        Ok(val) => val,
+        Err(_) => {
+            println!("[ERROR] Failed to parse min variable: {}", inputs[2]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+    let zone = match parse_zone(&inputs[3]) {
+        Some(val) => val,
+        None => {
+            println!("[ERROR] Failed to parse zone: {}", inputs[3]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    // Answer the request locally if the response is cached
+    let cache_key = inputs.join(" ");
+    if let Some(result) = get_cached_response(&options, &cache_key) {
+        println!(
+            "[INFO] getNumberofCountries with citycount: {} min: {}, Result: {}, (served from client cache)",
+            citycount, min, result
+        );
+        return Ok(RequestTiming::cached(result.into()));
+    }
+
+    // Connect to server:
+    let (server_addr, server_id) = match server_address(&options, zone) {
+        Some(server) => server,
+        None => {
+            println!("[ERROR] No server in zone {}", zone);
+            return Err(Status::invalid_argument("No server in the requested zone"));
+        }
+    };
+    let mut client = match connect(&options, server_addr, client_zone as u32, zone).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[ERROR] Failed to connect to server: {}", e);
+            return Err(Status::internal("Failed to connect to server"));
+        }
+    };
+
+    let mut request = Request::new(NumberOfCountriesRequest { citycount, min });
+
+    // Set zone data as meta data in the request
+    request
+        .metadata_mut()
+        .insert("client_zone", MetadataValue::from(client_zone));
+
+    request
+        .metadata_mut()
+        .insert("request_zone", MetadataValue::from(zone));
+
+    // Key for the simulated network latency, so the request gets the same delay in every run
+    request
+        .metadata_mut()
+        .insert(REQUEST_KEY_HEADER, MetadataValue::from(request_index));
+
+    // Capture the start time, or use the intended send time in open-loop mode
+    let start = send_time.unwrap_or_else(Instant::now);
+
+    // Get the response
+    let response: Response<NumberOfCountriesResponse> =
+        match client.get_number_of_countries(request).await {
+            Ok(response) => response,
+            Err(status) => {
+                log_failure(zone, &status);
+                return Err(status);
+            }
+        };
+
+    // Calculate turn around, execution and wait time
+    let turnaround_time = start.elapsed();
+    let execution_ms = response
+        .metadata()
+        .get("execution")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let network_ms = network_delay(response.metadata());
+    let waiting_ms: u64 =
+        (turnaround_time.as_millis() as u64).saturating_sub(execution_ms + network_ms);
+    let cache_status = cache_status(response.metadata());
+    let server = ServerInfo::from_metadata(response.metadata(), server_id);
+
+    let result: i32 = response.get_ref().result;
+
+    // Cache the response for repeated requests
+    cache_response(&options, cache_key, server.id, response.metadata(), result);
+
+    // Print the result
+    println!("[INFO] getNumberofCountries with citycount: {} min: {}, Result: {}, (turnaround time: {} ms, execution time:
+{} ms, waiting time: {} ms, network time: {} ms, processed by {})", citycount, min, result, turnaround_time.as_millis(), execution_ms, waiting_ms, network_ms, server);
+
+    Ok(RequestTiming {
+        turnaround_ms: turnaround_time.as_millis() as u64,
+        execution_ms,
+        waiting_ms,
+        network_ms,
+        value: result.into(),
+        cache_status,
+        server,
+        cached: false,
+    })
+}
+
+/// Create a connection to given server and sends request.
+///
+/// Sends a gRPC request for getting the number of countries that has the given amount of cities where each city has a given minimum population and less than a given maximum population.
+/// Needs the zone number of the client, index of the request in the file, intended send time (open-loop mode), vec of inputs and the client options.
+/// Without a proxy the request is sent directly to a server in the requested zone.
+/// Repeated requests are answered from the client cache when it is enabled.
+/// Should be used in a thread. Does not crash or panic the program.
+async fn create_client_and_get_number_of_countries_max(
+    client_zone: i32,
+    request_index: u64,
+    send_time: Option<Instant>,
+    inputs: Vec<String>,
+    options: Arc<ClientOptions>,
+) -> Result<RequestTiming, Status> {
+    // Get variables
+    assert!(inputs.len() == 5);
+    let citycount = match inputs[1].parse::<i32>() {
+        Ok(val) => val,
+        Err(_) => {
+            println!("[ERROR] Failed to parse min variable: {}", inputs[1]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    let min = match inputs[2].parse::<i32>() {
+        Ok(val) => val,
+        Err(_) => {
+            println!("[ERROR] Failed to parse min variable: {}", inputs[2]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    let max = match inputs[3].parse::<i32>() {
+        Ok(val) => val,
+        Err(_) => {
+            println!("[ERROR] Failed to parse min variable: {}", inputs[3]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    let zone = match parse_zone(&inputs[4]) {
+        Some(val) => val,
+        None => {
+            println!("[ERROR] Failed to parse zone: {}", inputs[4]);
+            return Err(Status::invalid_argument("Failed to parse request"));
+        }
+    };
+
+    // Answer the request locally if the response is cached
+    let cache_key = inputs.join(" ");
+    if let Some(result) = get_cached_response(&options, &cache_key) {
+        println!(
+            "[INFO] getNumberofCountries with citycount: {} min: {}, max: {} Result: {}, (served from client cache)",
+            citycount, min, max, result
+        );
+        return Ok(RequestTiming::cached(result.into()));
+    }
+
+    // Connect to server
+    let (server_addr, server_id) = match server_address(&options, zone) {
+        Some(server) => server,
+        None => {
+            println!("[ERROR] No server in zone {}", zone);
+            return Err(Status::invalid_argument("No server in the requested zone"));
+        }
+    };
+    let mut client = match connect(&options, server_addr, client_zone as u32, zone).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[ERROR] Failed to connect to server: {}", e);
+            return Err(Status::internal("Failed to connect to server"));
+        }
+    };
+
+    let mut request = Request::new(NumberOfCountriesMaxRequest {
+        citycount,
+        min,
+        max,
+    });
+
+    // Set zone data as meta data in the request
+    request
+        .metadata_mut()
+        .insert("client_zone", MetadataValue::from(client_zone));
+
+    request
+        .metadata_mut()
+        .insert("request_zone", MetadataValue::from(zone));
+
+    // Key for the simulated network latency, so the request gets the same delay in every run
+    request
+        .metadata_mut()
+        .insert(REQUEST_KEY_HEADER, MetadataValue::from(request_index));
+
+    // Capture the start time, or use the intended send time in open-loop mode
+    let start = send_time.unwrap_or_else(Instant::now);
+
+    // Get the response
+    let response: Response<NumberOfCountriesMaxResponse> =
+        match client.get_number_of_countries_max(request).await {
+            Ok(response) => response,
+            Err(status) => {
+                log_failure(zone, &status);
+                return Err(status);
+            }
+        };
+
+    // Calculate turn around, execution and wait time
+    let turnaround_time = start.elapsed();
+    let execution_ms = response
+        .metadata()
+        .get("execution")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let network_ms = network_delay(response.metadata());
+    let waiting_ms: u64 =
+        (turnaround_time.as_millis() as u64).saturating_sub(execution_ms + network_ms);
+    let cache_status = cache_status(response.metadata());
+    let server = ServerInfo::from_metadata(response.metadata(), server_id);
+
+    let result: i32 = response.get_ref().result;
+
+    // Cache the response for repeated requests
+    cache_response(&options, cache_key, server.id, response.metadata(), result);
+
+    // Print the result
+    println!("[INFO] getNumberofCountries with citycount: {} min: {}, max: {} Result: {}, (turnaround time: {} ms, execution time:
+{} ms, waiting time: {} ms, network time: {} ms, processed by {})", citycount, min, max, result, turnaround_time.as_millis(), execution_ms, waiting_ms, network_ms, server);
+
+    Ok(RequestTiming {
+        turnaround_ms: turnaround_time.as_millis() as u64,
+        execution_ms,
+        waiting_ms,
+        network_ms,
+        value: result.into(),
+        cache_status,
+        server,
+        cached: false,
+    })
+}
+
+/// Timings of a completed request in milliseconds.
+#[derive(Debug, Clone, Copy, Default)]
+struct RequestTiming {
+    turnaround_ms: u64,
+    execution_ms: u64,
+    waiting_ms: u64,
+    /// Simulated network delay included in the turnaround time.
+    network_ms: u64,
+    /// Result returned by the server or the client cache.
+    value: i64,
+    /// Cache status written to the client log.
+    cache_status: &'static str,
+    /// Server that handled the request.
+    server: ServerInfo,
+    /// True if the request was answered from the client cache.
+    cached: bool,
+}
+
+impl RequestTiming {
+    /// Timing of a request answered from the client cache.
+    fn cached(value: i64) -> Self {
+        RequestTiming {
+            value,
+            cache_status: "client",
+            cached: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Identity and load of the server that handled a request, from the response metadata.
+#[derive(Debug, Clone, Copy, Default)]
+struct ServerInfo {
+    id: Option<u32>,
+    zone: Option<u32>,
+    /// Requests in flight on the server when it responded, including this one.
+    in_flight: Option<u64>,
+    /// Sequence number of the request on the server.
+    sequence: Option<u64>,
+}
+
+impl ServerInfo {
+    /// Read the server metadata of a response.
+    ///
+    /// The id falls back to the server the request was sent to, for servers that do not send it.
+    fn from_metadata(metadata: &MetadataMap, sent_to: Option<u32>) -> Self {
+        fn number<T: std::str::FromStr>(metadata: &MetadataMap, key: &str) -> Option<T> {
+            metadata
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<T>().ok())
+        }
+
+        ServerInfo {
+            id: number(metadata, "server_id").or(sent_to),
+            zone: number(metadata, "server_zone"),
+            in_flight: number(metadata, "in_flight"),
+            sequence: number(metadata, "sequence"),
+        }
+    }
+}
+
+impl fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "Server {}", id)?,
+            None => write!(f, "an unknown server")?,
+        }
+        if let Some(zone) = self.zone {
+            write!(f, " in zone {}", zone)?;
+        }
+        if let (Some(sequence), Some(in_flight)) = (self.sequence, self.in_flight) {
+            write!(f, " as request {} with {} in flight", sequence, in_flight)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of all requests of a single RPC type.
+#[derive(Debug, Default)]
+struct RpcReport {
+    sent: usize,
+    /// Requests never sent because the global timeout was reached.
+    unsent: usize,
+    successes: usize,
+    failures: usize,
+    timeouts: usize,
+    /// Number of failed requests per status code and error reason.
+    failure_codes: BTreeMap<String, usize>,
+    /// Successful requests with a different result than expected in the request file.
+    mismatches: usize,
+    cached: usize,
+    turnaround_ms: Vec<u64>,
+    execution_ms: Vec<u64>,
+    waiting_ms: Vec<u64>,
+    network_ms: Vec<u64>,
+}
+
+/// Outcome of all requests sent by the client, grouped by RPC type.
+#[derive(Debug, Default)]
+struct RunReport {
+    rpcs: BTreeMap<String, RpcReport>,
+}
+
+impl RunReport {
+    /// Get the report of the RPC type, adding it if missing.
+    fn rpc(&mut self, func_name: &str) -> &mut RpcReport {
+        self.rpcs.entry(func_name.to_string()).or_default()
+    }
+
+    /// Record the result of a finished request.
+    ///
+    /// Requests answered from the client cache are counted, but left out of the timings.
+    fn record(&mut self, func_name: &str, result: Result<RequestTiming, Status>) {
+        let rpc = self.rpc(func_name);
+        match result {
+            Ok(timing) if timing.cached => {
+                rpc.successes += 1;
+                rpc.cached += 1;
+            }
+            Ok(timing) => {
+                rpc.successes += 1;
+                rpc.turnaround_ms.push(timing.turnaround_ms);
+                rpc.execution_ms.push(timing.execution_ms);
+                rpc.waiting_ms.push(timing.waiting_ms);
+                rpc.network_ms.push(timing.network_ms);
+            }
+            Err(status) if status.code() == Code::DeadlineExceeded => rpc.timeouts += 1,
+            Err(status) => {
+                rpc.failures += 1;
+                *rpc.failure_codes.entry(failure_label(&status)).or_default() += 1;
+            }
+        }
+    }
+
+    /// Print the counts and timing distributions of every RPC type.
+    ///
+    /// Requests that were sent but never finished are counted as timed out.
+    fn print(&self, elapsed: Duration) {
+        let total: usize = self.rpcs.values().map(|rpc| rpc.sent + rpc.unsent).sum();
+        println!(
+            "[INFO] Run summary: {} requests in {:.1} s",
+            total,
+            elapsed.as_secs_f64()
+        );
+
+        for (func_name, rpc) in self.rpcs.iter() {
+            let finished = rpc.successes + rpc.failures + rpc.timeouts;
+            let timeouts = rpc.timeouts + rpc.unsent + rpc.sent.saturating_sub(finished);
+
+            println!(
+                "[INFO] {}: {} succeeded ({} from client cache), {} failed, {} timed out",
+                func_name, rpc.successes, rpc.cached, rpc.failures, timeouts
+            );
+            if !rpc.failure_codes.is_empty() {
+                let codes: Vec<String> = rpc
+                    .failure_codes
+                    .iter()
+                    .map(|(code, count)| format!("{} {}", code, count))
+                    .collect();
+                println!("[INFO]     failures: {}", codes.join(", "));
+            }
+            if rpc.mismatches > 0 {
+                println!(
+                    "[WARN]     {} results differ from the expected result",
+                    rpc.mismatches
+                );
+            }
+
+            let timings = [
+                ("turnaround", &rpc.turnaround_ms),
+                ("execution", &rpc.execution_ms),
+                ("waiting", &rpc.waiting_ms),
+                ("network", &rpc.network_ms),
+            ];
+            for (name, values) in timings {
+                if let Some(summary) = Summary::from_values(values) {
+                    println!("[INFO]     {} time (ms): {}", name, summary);
+                }
+            }
+        }
+    }
+}
+
+/// gRPC client, sending requests through the fault layer and the latency layer when it is enabled.
+type StatClient = StatMethodsClient<FaultService<Either<LatencyService<Channel>, Channel>>>;
+
+/// Options shared by all requests sent by the client.
+pub struct ClientOptions {
+    /// Zones and servers of the simulation.
+    topology: Topology,
+    /// Simulated network latency between the zones. Disabled if not set.
+    latency: Option<Arc<LatencyModel>>,
+    /// Injected network faults between the zones.
+    faults: Arc<FaultModel>,
+    /// Counter used to spread requests over the servers in a zone.
+    next_server: AtomicUsize,
+    /// Address of the proxy (with protocol). Requests are sent directly to the servers if not set.
+    proxy_addr: Option<String>,
+    /// Cache answering repeated requests locally. Disabled if not set.
+    cache: Option<Mutex<ResponseCache>>,
+    /// Time limit for the whole run. Requests still running are aborted when it is reached.
+    timeout: Option<Duration>,
+    /// Send requests on this schedule, independent of the responses. Closed-loop if not set.
+    open_loop: Option<ArrivalProfile>,
+    /// Seed of the open-loop arrival times.
+    arrival_seed: u64,
+    /// In-memory network to reach the servers on, when they run in the same process. TCP is used if not set.
+    network: Option<Arc<MemoryNetwork>>,
+}
+
+impl ClientOptions {
+    /// Connect to the servers over the given in-memory network instead of TCP.
+    pub fn with_network(mut self, network: Arc<MemoryNetwork>) -> Self {
+        self.network = Some(network);
+        self
+    }
+}
+
+/// Get the address (with protocol) a request for the given zone is sent to, and the id of the server.
+///
+/// This is the proxy if one is given, otherwise the servers of the zone are used round robin.
+/// Returns `None` if the zone has no servers.
+fn server_address(options: &ClientOptions, zone: u32) -> Option<(String, Option<u32>)> {
+    if let Some(proxy_addr) = &options.proxy_addr {
+        return Some((proxy_addr.clone(), None));
+    }
+
+    let counter = options.next_server.fetch_add(1, Ordering::Relaxed);
+    options
+        .topology
+        .pick_server(zone, counter)
+        .map(|server| (server.uri(), Some(server.id)))
+}
+
+/// Get the cached result of a request if the client cache is enabled.
+///
+/// A request answered from the cache is written to the log with cache status `client` and no turnaround time.
+fn get_cached_response(options: &ClientOptions, cache_key: &str) -> Option<i32> {
+    match &options.cache {
+        Some(cache) => cache.lock().unwrap().get(cache_key),
+        None => None,
+    }
+}
+
+/// Store the result of a request in the client cache if it is enabled.
+///
+/// Responses cached from the server are invalidated first if it sent a new cache epoch.
+/// Responses from an unknown server are not cached, as they could not be invalidated.
+fn cache_response(
+    options: &ClientOptions,
+    cache_key: String,
+    server_id: Option<u32>,
+    metadata: &MetadataMap,
+    value: i32,
+) {
+    let (Some(cache), Some(server_id)) = (&options.cache, server_id) else {
+        return;
+    };
+    let mut cache = cache.lock().unwrap();
+
+    if let Some(epoch) = metadata.get("cache_epoch").and_then(|v| v.to_str().ok()) {
+        if cache.observe_epoch(server_id, epoch) {
+            println!(
+                "[INFO] Server {} changed cache epoch, invalidated cached responses",
+                server_id
+            );
+        }
+    }
+
+    cache.insert(cache_key, server_id, value);
+}
+
+/// Get the simulated network delay of a call in milliseconds, or 0 if no latency was injected.
+fn network_delay(metadata: &MetadataMap) -> u64 {
+    metadata
+        .get(NETWORK_DELAY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0)
+}
+
+/// Connect to a server in the given zone.
+///
+/// Unless disabled, requests go through a latency layer simulating the network between the client zone and the server zone.
+/// Faults from the topology are injected before the latency, so failed requests are not delayed by it.
+async fn connect(
+    options: &ClientOptions,
+    server_addr: String,
+    client_zone: u32,
+    zone: u32,
+) -> Result<StatClient, tonic::transport::Error> {
+    let channel = match &options.network {
+        Some(network) => network.channel(server_addr).await?,
+        None => Endpoint::from_shared(server_addr)?.connect().await?,
+    };
+
+    let latency_layer = options
+        .latency
+        .as_ref()
+        .map(|model| LatencyLayer::client(model.clone(), client_zone, zone));
+
+    Ok(StatMethodsClient::new(
+        ServiceBuilder::new()
+            .layer(FaultLayer::new(options.faults.clone(), client_zone, zone))
+            .option_layer(latency_layer)
+            .service(channel),
+    ))
+}
+
+/// Get the cache status the server set in the response metadata.
+///
+/// Returns `hit` or `miss`, or `none` if the server does not cache results.
+fn cache_status(metadata: &MetadataMap) -> &'static str {
+    match metadata.get("cache").and_then(|value| value.to_str().ok()) {
+        Some("hit") => "hit",
+        Some("miss") => "miss",
+        _ => "none",
+    }
+}
+
+/// Log a failed request, with the error details the server sent.
+///
+/// Invalid requests list the rejected fields, unknown countries are only a warning,
+/// and overloaded servers give the delay after which the request may be retried.
+fn log_failure(zone: u32, status: &Status) {
+    let details = ErrorDetails::from_status(status);
+    match status.code() {
+        Code::InvalidArgument if !details.field_violations.is_empty() => {
+            let fields: Vec<String> = details
+                .field_violations
+                .iter()
+                .map(|(field, description)| format!("{} {}", field, description))
+                .collect();
+            println!(
+                "[ERROR] Request to zone {} was rejected as invalid: {}",
+                zone,
+                fields.join(", ")
+            );
+        }
+        Code::NotFound => match details.resource {
+            Some((resource_type, name)) => {
+                println!(
+                    "[WARN] Request to zone {}: {} {} not found",
+                    zone, resource_type, name
+                )
+            }
+            None => println!("[WARN] Request to zone {}: {}", zone, status.message()),
+        },
+        Code::ResourceExhausted | Code::Unavailable => {
+            let retry = details
+                .retry_after
+                .map(|delay| format!(", retry after {} ms", delay.as_millis()))
+                .unwrap_or_default();
+            println!(
+                "[ERROR] Request to zone {} failed with {:?}: {}{}",
+                zone,
+                status.code(),
+                status.message(),
+                retry
+            );
+        }
+        code => println!(
+            "[ERROR] Request to zone {} failed with {:?}: {}",
+            zone,
+            code,
+            status.message()
+        ),
+    }
+}
+
+/// Label of a failed request in the run summary: the status code, with the error reason if the server sent one.
+fn failure_label(status: &Status) -> String {
+    match ErrorDetails::from_status(status).reason {
+        Some(reason) => format!("{:?} ({})", status.code(), reason),
+        None => format!("{:?}", status.code()),
+    }
+}
+
+/// Build the client log record of a finished request.
+///
+/// Failed requests are logged with the time until they failed as turnaround time.
+fn log_record(
+    id: String,
+    inputs: &[String],
+    client_zone: i32,
+    sent_offset_ms: u64,
+    result: &Result<RequestTiming, Status>,
+    failed_after: Duration,
+) -> ClientLogRecord {
+    let parameters = match inputs {
+        [_, parameters @ .., _] => parameters.join(" "),
+        _ => String::new(),
+    };
+    let mut record = ClientLogRecord {
+        version: CLIENT_LOG_VERSION,
+        sent_offset_ms,
+        request_id: id,
+        rpc: inputs[0].clone(),
+        parameters,
+        client_zone,
+        target_zone: inputs.last().and_then(|zone| parse_zone(zone)),
+        server_id: None,
+        server_zone: None,
+        server_in_flight: None,
+        server_sequence: None,
+        cache_status: String::from("none"),
+        status_code: format!("{:?}", Code::Ok),
+        error_reason: None,
+        result: None,
+        turnaround_ms: 0,
+        execution_ms: 0,
+        waiting_ms: 0,
+        network_ms: 0,
+    };
+
+    match result {
+        Ok(timing) => {
+            record.server_id = timing.server.id;
+            record.server_zone = timing.server.zone;
+            record.server_in_flight = timing.server.in_flight;
+            record.server_sequence = timing.server.sequence;
+            record.cache_status = timing.cache_status.to_string();
+            record.result = Some(timing.value);
+            record.turnaround_ms = timing.turnaround_ms;
+            record.execution_ms = timing.execution_ms;
+            record.waiting_ms = timing.waiting_ms;
+            record.network_ms = timing.network_ms;
+        }
+        Err(status) => {
+            record.status_code = format!("{:?}", status.code());
+            record.error_reason = ErrorDetails::from_status(status).reason;
+            record.turnaround_ms = failed_after.as_millis() as u64;
+        }
+    }
+    record
+}
+
+/// Parse the optional client flags.
+///
+/// `--topology <path>` loads the zones and servers from the given file instead of `topology.toml`.
+/// `--proxy <addr>` sends all requests through the proxy.
+/// `--cache <capacity>` enables the client cache, and `--cache-ttl <seconds>` lets cached responses expire.
+/// `--timeout <seconds>` limits the time of the whole run.
+/// `--no-latency` turns off the simulated network latency, for when the servers inject it.
+/// `--open-loop <profile>` sends requests on a schedule instead of keeping 10 in flight,
+/// with `poisson:<rps>`, `step:<rps>:<seconds>,...` or `ramp:<from rps>:<to rps>:<seconds>` arrivals seeded by `--arrival-seed <seed>`.
+pub fn parse_options(flags: &[String]) -> Result<ClientOptions, Box<dyn Error>> {
+    let mut topology_path = String::from(DEFAULT_TOPOLOGY_PATH);
+    let mut proxy_addr: Option<String> = None;
+    let mut timeout: Option<Duration> = None;
+    let mut cache_capacity: Option<usize> = None;
+    let mut cache_ttl: Option<Duration> = None;
+    let mut open_loop: Option<ArrivalProfile> = None;
+    let mut arrival_seed: Option<u64> = None;
+
+    let mut inject_latency = true;
+
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        // Flags without a value
+        if flag == "--no-latency" {
+            inject_latency = false;
+            continue;
+        }
+
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", flag))?;
+
+        match flag.as_str() {
+            "--topology" => topology_path = value.clone(),
+            "--proxy" => proxy_addr = Some(value.clone()),
+            "--cache" => cache_capacity = Some(value.parse::<usize>()?),
+            "--cache-ttl" => cache_ttl = Some(Duration::from_secs(value.parse::<u64>()?)),
+            "--timeout" => timeout = Some(Duration::from_secs(value.parse::<u64>()?)),
+            "--open-loop" => open_loop = Some(value.parse::<ArrivalProfile>()?),
+            "--arrival-seed" => arrival_seed = Some(value.parse::<u64>()?),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    let topology = Topology::load(&topology_path)?;
+
+    Ok(ClientOptions {
+        latency: inject_latency.then(|| Arc::new(LatencyModel::new(topology.latency.clone()))),
+        faults: Arc::new(FaultModel::new(topology.faults.clone())),
+        topology,
+        next_server: AtomicUsize::new(0),
+        proxy_addr,
+        cache: cache_capacity.map(|capacity| Mutex::new(ResponseCache::new(capacity, cache_ttl))),
+        timeout,
+        open_loop,
+        arrival_seed: arrival_seed.unwrap_or_else(rand::random),
+        network: None,
+    })
+}
+
+/// Send every request of the request file and print the run summary.
+///
+/// Requests are sent from the given client zone, and every finished request is written to the client log of the zone.
+pub async fn run_client(
+    file_path: &str,
+    client_zone: i32,
+    options: Arc<ClientOptions>,
+) -> Result<(), Box<dyn Error>> {
+    // Open the file asynchronously
+    let mut file: File = File::open(file_path)?;
+
+    // Read the file contents into a string
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    let contents = Arc::new(contents);
+
+    // Replace the log of an earlier run
+    let mut log = ClientLogWriter::create(client_zone)?;
+
+    if options.topology.zone(client_zone as u32).is_none() {
+        println!(
+            "[WARN] Client zone {} is not defined in the topology",
+            client_zone
+        );
+    }
+
+    // Process the file contents
+    println!(
+        "[INFO] Client (ZONE:{}) started with file: {}",
+        client_zone, file_path
+    );
+    match &options.latency {
+        Some(latency) => println!("[INFO] Latency seed: {}", latency.seed()),
+        None => println!("[INFO] Simulated network latency is disabled"),
+    }
+    if options.faults.is_enabled() {
+        println!("[INFO] Fault injection seed: {}", options.faults.seed());
+    }
+
+    // Read the requests, in the text format or the versioned JSON Lines format
+    let request_file = RequestFile::parse(&contents)?;
+    for (line, reason) in &request_file.rejected {
+        println!(
+            "[ERR] Client found illegal request on line {}: {}",
+            line, reason
+        );
+    }
+
+    // The index in the file is the key of the simulated network latency of a request.
+    // Requests due at the same time are sent in order of priority, highest first.
+    let mut requests: Vec<(u64, PlannedRequest)> = request_file
+        .requests
+        .into_iter()
+        .enumerate()
+        .map(|(index, request)| (index as u64, request))
+        .collect();
+    requests.sort_by_key(|(_, request)| (request.send_at, Reverse(request.priority)));
+
+    // Create X amount of threads to simulate new clients connecting and doing a task
+    // Semaphore is created with a limited amount of permits allowed
+    let semaphore = Arc::new(Semaphore::new(10));
+
+    // In open-loop mode requests are sent at their arrival time, no matter how many are in flight
+    let mut arrivals = match options.open_loop.clone() {
+        Some(profile) => {
+            println!(
+                "[INFO] Open-loop arrivals: {} (seed {})",
+                profile, options.arrival_seed
+            );
+            Some(ArrivalSchedule::new(profile, options.arrival_seed)?)
+        }
+        None => None,
+    };
+    let mut max_lag = Duration::ZERO;
+
+    // Every request must finish before the deadline if a global timeout is given
+    let run_start = Instant::now();
+    let schedule_start = Instant::now();
+    let deadline = options.timeout.map(|timeout| schedule_start + timeout);
+
+    let mut report = RunReport::default();
+    let mut tasks: JoinSet<(String, Result<RequestTiming, Status>, bool, ClientLogRecord)> =
+        JoinSet::new();
+
+    for (request_index, request) in requests {
+        let PlannedRequest {
+            id,
+            inputs,
+            send_at,
+            deadline: request_deadline,
+            expected,
+            ..
+        } = request;
+        let func_name = inputs[0].clone();
+        let options = options.clone();
+
+        // The arrival schedule replaces the send times of the request file in open-loop mode
+        let send_at = match arrivals.as_mut() {
+            Some(arrivals) => match arrivals.next() {
+                Some(offset) => Some(offset),
+                None => {
+                    report.rpc(&func_name).unsent += 1;
+                    continue;
+                }
+            },
+            None => send_at,
+        };
+
+        // Wait until the request is due, unless it is due after the global timeout
+        let mut scheduled = None;
+        if let Some(send_at) = send_at {
+            let send_time = schedule_start + send_at;
+            if deadline.is_some_and(|deadline| send_time >= deadline) {
+                report.rpc(&func_name).unsent += 1;
+                continue;
+            }
+            tokio::time::sleep_until(send_time).await;
+            scheduled = Some(send_time);
+        }
+
+        let (permit, send_time) = if arrivals.is_some() {
+            // Latency is measured from the intended send time, so falling behind the schedule is not hidden
+            if let Some(scheduled) = scheduled {
+                max_lag = max_lag.max(scheduled.elapsed());
+            }
+            (None, scheduled)
+        } else {
+            // Requests not sent before the deadline are counted as timed out
+            let permit = match deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, semaphore.clone().acquire_owned()).await
+                    {
+                        Ok(permit) => permit.unwrap(),
+                        Err(_) => {
+                            report.rpc(&func_name).unsent += 1;
+                            continue;
+                        }
+                    }
+                }
+                None => semaphore.clone().acquire_owned().await.unwrap(),
+            };
+            (Some(permit), None)
+        };
+        report.rpc(&func_name).sent += 1;
+
+        tasks.spawn(async move {
+            let sent = send_time.unwrap_or_else(Instant::now);
+            let sent_offset_ms = sent.duration_since(schedule_start).as_millis() as u64;
+            let log_inputs = inputs.clone();
+
+            // Send requests based on the different function types
+            let call = async {
+                match func_name.as_str() {
+                    "getPopulationofCountry" => {
+                        create_client_and_get_population_of_country(
+                            client_zone,
+                            request_index,
+                            send_time,
+                            inputs,
+                            options,
+                        )
+                        .await
+                    }
+                    "getNumberofCities" => {
+                        create_client_and_get_number_of_cities(
+                            client_zone,
+                            request_index,
+                            send_time,
+                            inputs,
+                            options,
+                        )
+                        .await
+                    }
+                    "getNumberofCountries" => {
+                        create_client_and_get_number_of_countries(
+                            client_zone,
+                            request_index,
+                            send_time,
+                            inputs,
+                            options,
+                        )
+                        .await
+                    }
+                    "getNumberofCountriesMax" => {
+                        create_client_and_get_number_of_countries_max(
+                            client_zone,
+                            request_index,
+                            send_time,
+                            inputs,
+                            options,
+                        )
+                        .await
+                    }
+                    unknown => {
+                        println!("[ERROR] Unknown function name: {unknown}");
+                        Err(Status::invalid_argument("Unknown function name"))
+                    }
+                }
+            };
+
+            // Requests with a deadline are cancelled when it is reached
+            let result = match request_deadline {
+                Some(limit) => match tokio::time::timeout(limit, call).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        println!(
+                            "[ERROR] Request {} missed its deadline of {} ms",
+                            id,
+                            limit.as_millis()
+                        );
+                        Err(Status::deadline_exceeded("Request deadline exceeded"))
+                    }
+                },
+                None => call.await,
+            };
+
+            // Compare the result with the expected result from the request file
+            let mismatch = match (&result, expected) {
+                (Ok(timing), Some(expected)) if timing.value != expected => {
+                    println!(
+                        "[WARN] Request {} returned {}, expected {}",
+                        id, timing.value, expected
+                    );
+                    true
+                }
+                _ => false,
+            };
+
+            // Drop the permit
+            drop(permit);
+
+            let record = log_record(
+                id,
+                &log_inputs,
+                client_zone,
+                sent_offset_ms,
+                &result,
+                sent.elapsed(),
+            );
+            (func_name, result, mismatch, record)
+        });
+    }
+
+    if max_lag > Duration::from_millis(10) {
+        println!(
+            "[WARN] Sending fell up to {} ms behind the arrival schedule",
+            max_lag.as_millis()
+        );
+    }
+
+    // Wait for every request, or until the deadline
+    loop {
+        let next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, tasks.join_next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    println!(
+                        "[ERROR] Global timeout reached with {} requests in flight",
+                        tasks.len()
+                    );
+                    tasks.abort_all();
+                    break;
+                }
+            },
+            None => tasks.join_next().await,
+        };
+
+        match next {
+            Some(Ok((func_name, result, mismatch, record))) => {
+                if let Err(e) = log.write(&record) {
+                    println!("[ERROR] Was not able to write to the client log: {}", e);
+                }
+                report.record(&func_name, result);
+                if mismatch {
+                    report.rpc(&func_name).mismatches += 1;
+                }
+            }
+            Some(Err(e)) => println!("[ERROR] Request task failed: {}", e),
+            None => break,
+        }
+    }
+
+    report.print(run_start.elapsed());
+
+    Ok(())
+}
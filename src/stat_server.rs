@@ -0,0 +1,1795 @@
+//! gRPC server of the statistics service, run by the `server` binary and by `simulate`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cache::{Cache, EvictionPolicy};
+use crate::dataset::{City, PopulationRange, SELECT_CITIES};
+use crate::db_pool::{ConnectionPool, PoolConfig, PoolError, PooledResult};
+use crate::geo_index::{CityDistance, GeoIndex};
+use crate::latency::{LatencyModel, ServiceTimeModel};
+use crate::latency_layer::{LatencyLayer, REQUEST_KEY_HEADER};
+use crate::memory_transport::MemoryNetwork;
+use crate::proto::stat_service::stat_methods_server::{StatMethods, StatMethodsServer};
+use crate::proto::stat_service::{
+    Empty, NumberOfCitiesRequest, NumberOfCitiesResponse, NumberOfCountriesMaxRequest,
+    NumberOfCountriesMaxResponse, NumberOfCountriesRequest, NumberOfCountriesResponse,
+    PopulationRequest, PopulationResponse, RecordsResponse,
+};
+use crate::proto::stat_service_v2 as v2;
+use crate::proto::stat_service_v2::stat_methods_server::{
+    StatMethods as StatMethodsV2, StatMethodsServer as StatMethodsV2Server,
+};
+use crate::rpc_error::StatError;
+use crate::topology::{Topology, DEFAULT_TOPOLOGY_PATH};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, Row};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::MetadataValue;
+
+use tonic::{transport::Server, Request, Response, Status};
+use tower::util::option_layer;
+
+pub struct StatServer {
+    /// Id of the server in the topology.
+    id: u32,
+    /// Zone of the server in the topology.
+    zone: u32,
+    /// Number of requests being handled, including streams still sending.
+    in_flight: Arc<AtomicUsize>,
+    /// Sequence number given to the next request.
+    next_sequence: AtomicU64,
+    /// Runs the queries on the read-only connections to the city database.
+    queries: QueryRunner,
+    /// Query results keyed by RPC name and request parameters. `None` if caching is disabled.
+    cache: Option<Mutex<Cache<String, i64>>>,
+    /// Cache epoch sent to the clients, incremented by `invalidate`.
+    epoch: AtomicU64,
+    /// Requests in flight beyond which new requests are rejected. Unlimited if not set.
+    max_in_flight: Option<usize>,
+    /// Spatial index of the cities, with the cache epoch of the database it was built from.
+    /// Built on the first geospatial request.
+    geo_index: tokio::sync::Mutex<Option<(u64, Arc<GeoIndex>)>>,
+}
+
+impl StatServer {
+    /// Create the server with the given id and zone, running queries on the connection pool, without a result cache.
+    pub fn new(id: u32, zone: u32, pool: ConnectionPool) -> Self {
+        StatServer {
+            id,
+            zone,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            next_sequence: AtomicU64::new(0),
+            queries: QueryRunner {
+                pool: Arc::new(pool),
+                service_time: None,
+            },
+            cache: None,
+            epoch: AtomicU64::new(0),
+            max_in_flight: None,
+            geo_index: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Cache up to `capacity` query results with the given eviction policy.
+    pub fn with_cache(mut self, capacity: usize, policy: EvictionPolicy) -> Self {
+        self.cache = Some(Mutex::new(Cache::new(capacity, policy)));
+        self
+    }
+
+    /// Add the simulated service time to every query.
+    pub fn with_service_time(mut self, model: ServiceTimeModel) -> Self {
+        self.queries.service_time = Some(Arc::new(model));
+        self
+    }
+
+    /// Reject requests beyond the given number in flight with `UNAVAILABLE`.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Invalidate everything derived from the database, after it was rebuilt.
+    ///
+    /// The connections are reopened on the new file, cached results and the spatial index are dropped,
+    /// and the cache epoch is incremented so clients drop the responses they cached from this server.
+    /// Returns the new cache epoch.
+    pub async fn invalidate(&self) -> u64 {
+        self.queries.pool.reopen();
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().clear();
+        }
+        *self.geo_index.lock().await = None;
+
+        println!(
+            "[INFO] Reopened {} and invalidated cached results, cache epoch is now {}",
+            self.queries.pool.path(),
+            epoch
+        );
+        epoch
+    }
+
+    /// Get the current cache epoch.
+    fn cache_epoch(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Count a request as in flight until the returned guard is dropped, and give it the next sequence number.
+    ///
+    /// Fails if the server already has the maximum number of requests in flight.
+    fn begin_request(&self) -> Result<InFlightGuard, StatError> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlightGuard {
+            in_flight: self.in_flight.clone(),
+            sequence: self.next_sequence.fetch_add(1, Ordering::SeqCst),
+        };
+
+        if self.max_in_flight.is_some_and(|max| in_flight >= max) {
+            println!(
+                "[WARN] Rejected request with {} requests in flight",
+                in_flight
+            );
+            return Err(StatError::Overloaded {
+                retry_after: RETRY_AFTER,
+            });
+        }
+        Ok(guard)
+    }
+
+    /// Run a query returning a single count on the connection pool.
+    async fn query_count(
+        &self,
+        query: &'static str,
+        params: Vec<Value>,
+        request_key: u64,
+    ) -> Result<PooledResult<i64>, StatError> {
+        self.query_row(query, params, request_key, |row| row.get(0))
+            .await
+    }
+
+    /// Run a query returning a single row on the connection pool, and read the row with `map`.
+    ///
+    /// Uses the prepared statement cache of the connection.
+    async fn query_row<T: Send + 'static>(
+        &self,
+        query: &'static str,
+        params: Vec<Value>,
+        request_key: u64,
+        map: fn(&Row) -> rusqlite::Result<T>,
+    ) -> Result<PooledResult<T>, StatError> {
+        self.queries
+            .run(request_key, move |connection| {
+                connection
+                    .prepare_cached(query)?
+                    .query_row(params_from_iter(params), map)
+            })
+            .await
+    }
+
+    /// Get the spatial index of the cities, building it on the connection pool
+    /// on the first request and whenever the cache epoch changed since it was built.
+    async fn geo_index(&self) -> Result<Arc<GeoIndex>, StatError> {
+        let mut geo_index = self.geo_index.lock().await;
+
+        let epoch = self.cache_epoch();
+        if let Some((built_epoch, index)) = geo_index.as_ref() {
+            if *built_epoch == epoch {
+                return Ok(index.clone());
+            }
+        }
+
+        let start = Instant::now();
+        let index = Arc::new(
+            self.queries
+                .pool
+                .run(GeoIndex::load)
+                .await
+                .map_err(pool_error)?
+                .value,
+        );
+        println!(
+            "[INFO] Built the spatial index of {} cities in {} ms",
+            index.len(),
+            start.elapsed().as_millis()
+        );
+
+        *geo_index = Some((epoch, index.clone()));
+        Ok(index)
+    }
+
+    /// Read the records of the cities with the given ids, keyed by id.
+    ///
+    /// Cities removed from the database are left out.
+    async fn cities_by_id(
+        &self,
+        ids: Vec<i64>,
+        request_key: u64,
+    ) -> Result<PooledResult<HashMap<i64, City>>, StatError> {
+        let query = format!(
+            "{} WHERE [Geoname ID] IN (SELECT value FROM json_each(?1))",
+            SELECT_CITIES
+        );
+        let ids = serde_json::to_string(&ids).map_err(|_| StatError::QueryFailed)?;
+
+        self.queries
+            .run(request_key, move |connection| {
+                connection
+                    .prepare_cached(&query)?
+                    .query_map([ids], City::from_row)?
+                    .map(|city| city.map(|city| (city.geoname_id, city)))
+                    .collect()
+            })
+            .await
+    }
+
+    /// Look up a cached query result.
+    fn cache_get(&self, key: &str) -> Option<i64> {
+        self.cache.as_ref()?.lock().unwrap().get(key)
+    }
+
+    /// Store the result of a query started in the given cache epoch.
+    ///
+    /// The result is dropped if the cache was invalidated while the query ran, as it may come from the old database.
+    fn cache_insert(&self, key: String, value: i64, epoch: u64) {
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().unwrap();
+            if self.cache_epoch() == epoch {
+                cache.insert(key, value);
+            }
+        }
+    }
+
+    /// Count the cities in the database.
+    async fn count_records(&self, request_key: u64) -> Result<QueryOutcome<i64>, StatError> {
+        // Check the cache before querying
+        let cache_key = String::from("GetRecordsCount");
+        if let Some(count) = self.cache_get(&cache_key) {
+            return Ok(QueryOutcome::cached(count));
+        }
+        let epoch = self.cache_epoch();
+
+        // Query for counting
+        let query = "SELECT COUNT(*) from cities";
+
+        // Execute the query on the connection pool
+        let result = self.query_count(query, vec![], request_key).await?;
+
+        self.cache_insert(cache_key, result.value, epoch);
+        Ok(QueryOutcome::queried(result.value, result.wait))
+    }
+
+    /// Sum the population of the cities of a country.
+    ///
+    /// The population is `None` if no city of the country has a known population. Such results are not cached.
+    async fn population_of_country(
+        &self,
+        country_name: &str,
+        request_key: u64,
+    ) -> Result<QueryOutcome<Option<i64>>, StatError> {
+        // Check the cache before querying
+        let cache_key = format!("GetPopulationOfCountry:{}", country_name);
+        if let Some(population) = self.cache_get(&cache_key) {
+            return Ok(QueryOutcome::cached(Some(population)));
+        }
+        let epoch = self.cache_epoch();
+
+        // Prepare the SQL query, also counting the cities to tell an unknown country from an unknown population
+        let query = "SELECT COUNT(*), SUM(Population) FROM cities WHERE [Country name EN] = ?1";
+
+        // Execute the query on the connection pool
+        let result = self
+            .query_row(
+                query,
+                vec![Value::from(country_name.to_string())],
+                request_key,
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?)),
+            )
+            .await?;
+        let (all_cities, population) = result.value;
+        if all_cities == 0 {
+            return Err(StatError::CountryNotFound(country_name.to_string()));
+        }
+
+        if let Some(population) = population {
+            self.cache_insert(cache_key, population, epoch);
+        }
+        Ok(QueryOutcome::queried(population, result.wait))
+    }
+
+    /// Count the cities of a country with a population over the minimum, or all its cities without a minimum.
+    async fn number_of_cities(
+        &self,
+        country_name: &str,
+        min_population: Option<i64>,
+        request_key: u64,
+    ) -> Result<QueryOutcome<i64>, StatError> {
+        // Check the cache before querying
+        let cache_key = format!(
+            "GetNumberOfCities:{}:{}",
+            country_name,
+            key_part(min_population)
+        );
+        if let Some(count) = self.cache_get(&cache_key) {
+            return Ok(QueryOutcome::cached(count));
+        }
+        let epoch = self.cache_epoch();
+
+        // Prepare the SQL query, also counting all cities to tell an unknown country from no matches
+        let query = "SELECT COUNT(*), COUNT(CASE WHEN ?2 IS NULL OR [Population] > ?2 THEN 1 END) FROM cities WHERE [Country name EN] = ?1";
+
+        // Execute the query on the connection pool
+        let result = self
+            .query_row(
+                query,
+                vec![
+                    Value::from(country_name.to_string()),
+                    Value::from(min_population),
+                ],
+                request_key,
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .await?;
+        let (all_cities, city_count) = result.value;
+        if all_cities == 0 {
+            return Err(StatError::CountryNotFound(country_name.to_string()));
+        }
+
+        self.cache_insert(cache_key, city_count, epoch);
+        Ok(QueryOutcome::queried(city_count, result.wait))
+    }
+
+    /// Count the countries with more than `city_count` cities, where every city has a population
+    /// over the minimum and below the maximum. A missing bound is not checked.
+    async fn number_of_countries(
+        &self,
+        city_count: i64,
+        min_population: Option<i64>,
+        max_population: Option<i64>,
+        request_key: u64,
+    ) -> Result<QueryOutcome<i64>, StatError> {
+        // Check the cache before querying
+        let cache_key = format!(
+            "GetNumberOfCountries:{}:{}:{}",
+            city_count,
+            key_part(min_population),
+            key_part(max_population)
+        );
+        if let Some(count) = self.cache_get(&cache_key) {
+            return Ok(QueryOutcome::cached(count));
+        }
+        let epoch = self.cache_epoch();
+
+        // Query for collecting all
+        let query = "SELECT COUNT(*) FROM (SELECT COUNT(*) as citycount, MIN([Population]) as min, MAX([Population]) as max FROM cities GROUP BY [Country name EN] HAVING citycount > ?1 and (?2 IS NULL or min > ?2) and (?3 IS NULL or max < ?3))";
+
+        // Execute the query on the connection pool
+        let result = self
+            .query_count(
+                query,
+                vec![
+                    Value::from(city_count),
+                    Value::from(min_population),
+                    Value::from(max_population),
+                ],
+                request_key,
+            )
+            .await?;
+
+        self.cache_insert(cache_key, result.value, epoch);
+        Ok(QueryOutcome::queried(result.value, result.wait))
+    }
+
+    /// Insert execution time, server identity, cache status, cache epoch and pool usage as metadata in the response.
+    ///
+    /// The `server_id` and `server_zone` keys identify the server, `in_flight` is the number of requests
+    /// it is handling, including this one, and `sequence` is the number of requests it received before this one.
+    /// The `cache` key is `hit` or `miss`, and is left out when caching is disabled.
+    /// The `cache_epoch` key lets clients invalidate responses they cached from this server.
+    /// The `pool_wait` key is the time in ms spent waiting for a database connection.
+    fn insert_metadata<T>(
+        &self,
+        response: &mut Response<T>,
+        start: Instant,
+        request: &InFlightGuard,
+        cache_hit: bool,
+        pool_wait: Duration,
+    ) {
+        // Get the execution time
+        let execution_ms = start.elapsed().as_millis() as u64;
+
+        // Insert execution as metadata
+        response
+            .metadata_mut()
+            .insert("execution", MetadataValue::from(execution_ms));
+
+        // Insert the identity and load of the server
+        let metadata = response.metadata_mut();
+        metadata.insert("server_id", MetadataValue::from(self.id));
+        metadata.insert("server_zone", MetadataValue::from(self.zone));
+        metadata.insert(
+            "in_flight",
+            MetadataValue::from(self.in_flight.load(Ordering::SeqCst) as u64),
+        );
+        metadata.insert("sequence", MetadataValue::from(request.sequence));
+
+        if self.cache.is_some() {
+            let status = if cache_hit { "hit" } else { "miss" };
+            response
+                .metadata_mut()
+                .insert("cache", MetadataValue::from_static(status));
+        }
+
+        response
+            .metadata_mut()
+            .insert("cache_epoch", MetadataValue::from(self.cache_epoch()));
+
+        // Insert the usage of the connection pool
+        let stats = self.queries.pool.stats();
+        let metadata = response.metadata_mut();
+        metadata.insert(
+            "pool_wait",
+            MetadataValue::from(pool_wait.as_millis() as u64),
+        );
+        metadata.insert("pool_in_use", MetadataValue::from(stats.in_use as u64));
+        metadata.insert("pool_waiting", MetadataValue::from(stats.waiting as u64));
+    }
+}
+
+/// Time clients are asked to wait before retrying a request rejected because the server is overloaded.
+const RETRY_AFTER: Duration = Duration::from_millis(100);
+
+/// Number of cities returned by a query without a limit.
+const DEFAULT_RESULT_LIMIT: u32 = 100;
+
+/// Largest number of cities returned by a single query.
+const MAX_RESULT_LIMIT: u32 = 1000;
+
+/// Number of batches of a listing read ahead of the client.
+const STREAM_BUFFER: usize = 2;
+
+/// Convert an error from the connection pool to the error sent to the client.
+fn pool_error(error: PoolError) -> StatError {
+    match error {
+        PoolError::Timeout => {
+            println!("[ERROR] Timed out waiting for a database connection");
+            StatError::PoolExhausted {
+                retry_after: RETRY_AFTER,
+            }
+        }
+        e => {
+            println!("[ERROR] Failed to execute query: {}", e);
+            StatError::QueryFailed
+        }
+    }
+}
+
+/// Add a field violation if the country name is empty.
+fn check_country(violations: &mut Vec<(String, String)>, country: &str) {
+    if country.trim().is_empty() {
+        violations.push((String::from("country"), String::from("must not be empty")));
+    }
+}
+
+/// Add a field violation if the value is not greater than 0.
+fn check_positive(violations: &mut Vec<(String, String)>, field: &str, value: i64) {
+    if value <= 0 {
+        violations.push((field.to_string(), String::from("must be greater than 0")));
+    }
+}
+
+/// Add a field violation if the value is negative.
+fn check_not_negative(violations: &mut Vec<(String, String)>, field: &str, value: i64) {
+    if value < 0 {
+        violations.push((field.to_string(), String::from("must not be negative")));
+    }
+}
+
+/// Add a field violation to the upper bound of a range if it is below the lower bound.
+fn check_range(
+    violations: &mut Vec<(String, String)>,
+    upper: &str,
+    lower: &str,
+    upper_below_lower: bool,
+) {
+    if upper_below_lower {
+        violations.push((
+            upper.to_string(),
+            format!("must not be less than {}", lower),
+        ));
+    }
+}
+
+/// Add field violations if the point is missing or outside the valid latitudes and longitudes.
+///
+/// Returns the latitude and longitude of the point, or 0 for a missing point.
+fn check_point(
+    violations: &mut Vec<(String, String)>,
+    field: &str,
+    point: Option<&v2::Point>,
+) -> (f64, f64) {
+    let Some(point) = point else {
+        violations.push((field.to_string(), String::from("must be set")));
+        return (0.0, 0.0);
+    };
+
+    if !(-90.0..=90.0).contains(&point.latitude) {
+        violations.push((
+            format!("{}.latitude", field),
+            String::from("must be between -90 and 90"),
+        ));
+    }
+    if !(-180.0..=180.0).contains(&point.longitude) {
+        violations.push((
+            format!("{}.longitude", field),
+            String::from("must be between -180 and 180"),
+        ));
+    }
+    (point.latitude, point.longitude)
+}
+
+/// Add field violations if a bound of the population range is negative, or the maximum is below the minimum.
+///
+/// A missing range has no bounds.
+fn check_population_range(
+    violations: &mut Vec<(String, String)>,
+    field: &str,
+    range: Option<&v2::PopulationRange>,
+) -> PopulationRange {
+    let range = PopulationRange {
+        min: range.and_then(|range| range.min),
+        max: range.and_then(|range| range.max),
+    };
+
+    if let Some(min) = range.min {
+        check_not_negative(violations, &format!("{}.min", field), min);
+    }
+    if let Some(max) = range.max {
+        check_not_negative(violations, &format!("{}.max", field), max);
+    }
+    if let (Some(min), Some(max)) = (range.min, range.max) {
+        check_range(
+            violations,
+            &format!("{}.max", field),
+            &format!("{}.min", field),
+            max < min,
+        );
+    }
+    range
+}
+
+/// Add a field violation if the number of results is 0 or over `MAX_RESULT_LIMIT`.
+///
+/// Returns the number of results, `DEFAULT_RESULT_LIMIT` if not given.
+fn check_limit(violations: &mut Vec<(String, String)>, field: &str, limit: Option<u32>) -> usize {
+    let limit = limit.unwrap_or(DEFAULT_RESULT_LIMIT);
+    if limit == 0 || limit > MAX_RESULT_LIMIT {
+        violations.push((
+            field.to_string(),
+            format!("must be between 1 and {}", MAX_RESULT_LIMIT),
+        ));
+    }
+    limit as usize
+}
+
+/// Add a field violation if the value does not fit in an `int64`, as the database stores it.
+fn check_int64(violations: &mut Vec<(String, String)>, field: &str, value: u64) -> i64 {
+    i64::try_from(value).unwrap_or_else(|_| {
+        violations.push((
+            field.to_string(),
+            String::from("must not be greater than the largest int64"),
+        ));
+        i64::MAX
+    })
+}
+
+/// Add field violations if the limit or the offset of the page is not valid.
+///
+/// Returns the limit and the offset, with `DEFAULT_RESULT_LIMIT` if no limit is given.
+fn check_page(violations: &mut Vec<(String, String)>, page: Option<&v2::Page>) -> (usize, i64) {
+    let limit = check_limit(violations, "page.limit", page.and_then(|page| page.limit));
+    let offset = check_int64(
+        violations,
+        "page.offset",
+        page.map_or(0, |page| page.offset),
+    );
+    (limit, offset)
+}
+
+/// Add a field violation if the value of an enum field is not known.
+///
+/// Returns the value, or the default value of the enum if it is not known.
+fn check_enum<E: TryFrom<i32> + Default>(
+    violations: &mut Vec<(String, String)>,
+    field: &str,
+    value: i32,
+) -> E {
+    E::try_from(value).unwrap_or_else(|_| {
+        violations.push((field.to_string(), format!("has no value {}", value)));
+        E::default()
+    })
+}
+
+/// Reject the request with `INVALID_ARGUMENT` if any field is not valid.
+fn reject_invalid(violations: Vec<(String, String)>) -> Result<(), StatError> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let error = StatError::InvalidArgument(violations);
+    println!("[ERROR] {}", error);
+    Err(error)
+}
+
+/// Convert a result to the `int32` fields of version 1 of the service.
+///
+/// Fails with `OUT_OF_RANGE` if it does not fit, as for the population of the largest countries.
+fn to_int32(field: &str, value: i64) -> Result<i32, StatError> {
+    i32::try_from(value).map_err(|_| {
+        let error = StatError::ResultOutOfRange(field.to_string());
+        println!("[ERROR] {}", error);
+        error
+    })
+}
+
+/// Build the message of a city record.
+fn city_message(city: City) -> v2::City {
+    v2::City {
+        geoname_id: city.geoname_id,
+        name: city.name,
+        ascii_name: city.ascii_name,
+        country_code: city.country_code,
+        country: city.country,
+        admin1_code: city.admin1_code,
+        population: city.population,
+        elevation: city.elevation,
+        timezone: city.timezone,
+        latitude: city.latitude,
+        longitude: city.longitude,
+    }
+}
+
+/// Build the messages of the found cities with their distance, in the order they were found.
+///
+/// Cities without a record are left out, as they were removed from the database after the index was built.
+fn city_distances(
+    found: &[CityDistance],
+    mut records: HashMap<i64, City>,
+) -> Vec<v2::CityDistance> {
+    found
+        .iter()
+        .filter_map(|found| {
+            let city = records.remove(&found.city.geoname_id)?;
+            Some(v2::CityDistance {
+                city: Some(city_message(city)),
+                distance_km: found.distance_km,
+            })
+        })
+        .collect()
+}
+
+/// SQL keyword of the sort direction.
+fn sort_keyword(direction: v2::SortDirection) -> &'static str {
+    match direction {
+        v2::SortDirection::Descending => "DESC",
+        v2::SortDirection::Ascending => "ASC",
+    }
+}
+
+/// Offset of the page after the records returned, or `None` if there are no more.
+fn next_offset(offset: i64, returned: usize, total_matches: u64) -> Option<u64> {
+    let next = offset as u64 + returned as u64;
+    (next < total_matches).then_some(next)
+}
+
+/// Part of a cache key for an optional parameter, `-` if it is not set.
+fn key_part(value: Option<i64>) -> String {
+    value.map_or_else(|| String::from("-"), |value| value.to_string())
+}
+
+/// Result of a query, answered from the cache or by the database.
+struct QueryOutcome<T> {
+    value: T,
+    cache_hit: bool,
+    /// Time spent waiting for a database connection.
+    pool_wait: Duration,
+}
+
+impl<T> QueryOutcome<T> {
+    fn cached(value: T) -> Self {
+        QueryOutcome {
+            value,
+            cache_hit: true,
+            pool_wait: Duration::ZERO,
+        }
+    }
+
+    fn queried(value: T, pool_wait: Duration) -> Self {
+        QueryOutcome {
+            value,
+            cache_hit: false,
+            pool_wait,
+        }
+    }
+}
+
+/// Runs queries on the connection pool, adding the simulated service time.
+///
+/// Cloned into the tasks that send streamed responses.
+#[derive(Clone)]
+struct QueryRunner {
+    pool: Arc<ConnectionPool>,
+    /// Simulated time spent on each query. Disabled if not set.
+    service_time: Option<Arc<ServiceTimeModel>>,
+}
+
+impl QueryRunner {
+    /// Run queries on a connection of the pool.
+    ///
+    /// The simulated service time of the request is added after the queries.
+    async fn run<T, F>(&self, request_key: u64, query: F) -> Result<PooledResult<T>, StatError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let stats = self.pool.stats();
+        if stats.is_saturated() {
+            println!(
+                "[WARN] Connection pool saturated ({} of {} in use, {} waiting)",
+                stats.in_use, stats.size, stats.waiting
+            );
+        }
+
+        let result = self.pool.run(query).await.map_err(pool_error)?;
+        if let Some(model) = &self.service_time {
+            tokio::time::sleep(model.sample(request_key)).await;
+        }
+        Ok(result)
+    }
+}
+
+/// Filters of a listing of cities. A filter that is not set is not checked.
+struct CityFilter {
+    country: Option<String>,
+    population: PopulationRange,
+    admin1_code: Option<String>,
+}
+
+/// Send the cities matching the filter in batches ordered by geoname id, until every city is sent or the client is gone.
+///
+/// Each batch is read after the last geoname id of the previous batch, so no connection is held between batches.
+/// The channel holds at most `STREAM_BUFFER` batches, so no more is read while the client does not keep up.
+async fn stream_cities(
+    queries: QueryRunner,
+    filter: CityFilter,
+    batch_size: usize,
+    request_key: u64,
+    sender: mpsc::Sender<Result<v2::ListCitiesResponse, Status>>,
+    _in_flight: InFlightGuard,
+) {
+    let query = format!(
+        "{} WHERE [Geoname ID] > ?1 AND (?2 IS NULL OR [Country name EN] = ?2) AND (?3 IS NULL OR [Population] >= ?3) AND (?4 IS NULL OR [Population] <= ?4) AND (?5 IS NULL OR [Admin1 Code] = ?5) ORDER BY [Geoname ID] LIMIT ?6",
+        SELECT_CITIES
+    );
+
+    let mut after = i64::MIN;
+    let mut batches: u64 = 0;
+    let mut sent = 0;
+    loop {
+        let query = query.clone();
+        let params = vec![
+            Value::from(after),
+            Value::from(filter.country.clone()),
+            Value::from(filter.population.min),
+            Value::from(filter.population.max),
+            Value::from(filter.admin1_code.clone()),
+            Value::from(batch_size as i64),
+        ];
+
+        // Every batch gets its own service time
+        let result = queries
+            .run(request_key.wrapping_add(batches), move |connection| {
+                connection
+                    .prepare_cached(&query)?
+                    .query_map(params_from_iter(params), City::from_row)?
+                    .collect::<rusqlite::Result<Vec<City>>>()
+            })
+            .await;
+        let cities = match result {
+            Ok(result) => result.value,
+            Err(error) => {
+                let _ = sender.send(Err(error.into())).await;
+                return;
+            }
+        };
+
+        let last_batch = cities.len() < batch_size;
+        if let Some(last) = cities.last() {
+            after = last.geoname_id;
+            sent += cities.len();
+            batches += 1;
+
+            let batch = v2::ListCitiesResponse {
+                cities: cities.into_iter().map(city_message).collect(),
+            };
+            if sender.send(Ok(batch)).await.is_err() {
+                println!(
+                    "[WARN] Client closed the listing after {} cities in {} batches",
+                    sent, batches
+                );
+                return;
+            }
+        }
+
+        if last_batch {
+            break;
+        }
+    }
+
+    println!("[INFO] Listed {} cities in {} batches", sent, batches);
+}
+
+/// Keeps a request counted as in flight on the server until dropped.
+struct InFlightGuard {
+    in_flight: Arc<AtomicUsize>,
+    /// Sequence number of the request on the server, starting at 0.
+    sequence: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Get the request key the client set in the request metadata, or 0 if it is missing.
+fn request_key<T>(request: &Request<T>) -> u64 {
+    request
+        .metadata()
+        .get(REQUEST_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0)
+}
+
+/// Invalidate the server every time the process receives SIGHUP, e.g. after the database was rebuilt.
+#[cfg(unix)]
+async fn invalidate_on_hangup(server: Arc<StatServer>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            println!("[WARN] Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        server.invalidate().await;
+    }
+}
+
+#[cfg(not(unix))]
+async fn invalidate_on_hangup(_server: Arc<StatServer>) {}
+
+#[tonic::async_trait]
+impl StatMethods for StatServer {
+    async fn get_records_count(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<RecordsResponse>, Status> {
+        // Logging request
+        println!("[INFO] Request to count records..");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let outcome = self.count_records(request_key(&request)).await?;
+
+        let mut response = Response::new(RecordsResponse {
+            records: to_int32("records", outcome.value)?,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn get_population_of_country(
+        &self,
+        request: Request<PopulationRequest>,
+    ) -> Result<Response<PopulationResponse>, Status> {
+        // Logging request
+        println!("[INFO] Request to get population of the given country");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        // Retrieve country name from request
+        let country_name = &request.get_ref().country;
+        let mut violations = Vec::new();
+        check_country(&mut violations, country_name);
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .population_of_country(country_name, request_key(&request))
+            .await?;
+
+        // Create a response object. Version 1 cannot tell a population of 0 from an unknown population
+        let mut response = Response::new(PopulationResponse {
+            population: to_int32("population", outcome.value.unwrap_or(0))?,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn get_number_of_cities(
+        &self,
+        request: Request<NumberOfCitiesRequest>,
+    ) -> Result<Response<NumberOfCitiesResponse>, Status> {
+        // Logging request
+        println!("[INFO] Request to get number of cities with a minimum population");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        // Retrieve country name from request
+        let country_name = &request.get_ref().country;
+
+        // Retrieve minimum amount from request
+        let min = &request.get_ref().min;
+
+        let mut violations = Vec::new();
+        check_country(&mut violations, country_name);
+        check_not_negative(&mut violations, "min", (*min).into());
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .number_of_cities(country_name, Some((*min).into()), request_key(&request))
+            .await?;
+
+        // Create response
+        let mut response = Response::new(NumberOfCitiesResponse {
+            number_of_cities: to_int32("number_of_cities", outcome.value)?,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn get_number_of_countries(
+        &self,
+        request: Request<NumberOfCountriesRequest>,
+    ) -> Result<Response<NumberOfCountriesResponse>, Status> {
+        println!("[INFO] Request to get number of countries with a minimum population");
+
+        // Capture the start time
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        // Retrieve country name from request
+        let citycount: &i32 = &request.get_ref().citycount;
+        let min_population: &i32 = &request.get_ref().min;
+
+        // No need to query if the request is not good
+        let mut violations = Vec::new();
+        check_positive(&mut violations, "citycount", (*citycount).into());
+        check_positive(&mut violations, "min", (*min_population).into());
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .number_of_countries(
+                (*citycount).into(),
+                Some((*min_population).into()),
+                None,
+                request_key(&request),
+            )
+            .await?;
+
+        // Create the response
+        let mut response = Response::new(NumberOfCountriesResponse {
+            result: to_int32("result", outcome.value)?,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        // Return the response
+        Ok(response)
+    }
+
+    async fn get_number_of_countries_max(
+        &self,
+        request: Request<NumberOfCountriesMaxRequest>,
+    ) -> Result<Response<NumberOfCountriesMaxResponse>, Status> {
+        println!("[INFO] Request to get number of countries with a minimum population");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        // Retrieve country name from request
+        let citycount: &i32 = &request.get_ref().citycount;
+        let min_population: &i32 = &request.get_ref().min;
+        let max_population: &i32 = &request.get_ref().max;
+
+        // No need to query if the request is not good
+        let mut violations = Vec::new();
+        check_positive(&mut violations, "citycount", (*citycount).into());
+        check_positive(&mut violations, "min", (*min_population).into());
+        check_positive(&mut violations, "max", (*max_population).into());
+        check_range(
+            &mut violations,
+            "max",
+            "min",
+            max_population < min_population,
+        );
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .number_of_countries(
+                (*citycount).into(),
+                Some((*min_population).into()),
+                Some((*max_population).into()),
+                request_key(&request),
+            )
+            .await?;
+
+        let mut response = Response::new(NumberOfCountriesMaxResponse {
+            result: to_int32("result", outcome.value)?,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+}
+
+#[tonic::async_trait]
+impl StatMethodsV2 for StatServer {
+    async fn get_records_count(
+        &self,
+        request: Request<v2::GetRecordsCountRequest>,
+    ) -> Result<Response<v2::GetRecordsCountResponse>, Status> {
+        println!("[INFO] Request to count records (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let outcome = self.count_records(request_key(&request)).await?;
+
+        let mut response = Response::new(v2::GetRecordsCountResponse {
+            records: outcome.value as u64,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn get_population_of_country(
+        &self,
+        request: Request<v2::GetPopulationOfCountryRequest>,
+    ) -> Result<Response<v2::GetPopulationOfCountryResponse>, Status> {
+        println!("[INFO] Request to get population of the given country (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let country_name = &request.get_ref().country;
+        let mut violations = Vec::new();
+        check_country(&mut violations, country_name);
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .population_of_country(country_name, request_key(&request))
+            .await?;
+
+        let mut response = Response::new(v2::GetPopulationOfCountryResponse {
+            population: outcome.value,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn get_number_of_cities(
+        &self,
+        request: Request<v2::GetNumberOfCitiesRequest>,
+    ) -> Result<Response<v2::GetNumberOfCitiesResponse>, Status> {
+        println!("[INFO] Request to get number of cities (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let country_name = &request.get_ref().country;
+        let min_population = request.get_ref().min_population;
+
+        let mut violations = Vec::new();
+        check_country(&mut violations, country_name);
+        if let Some(min) = min_population {
+            check_not_negative(&mut violations, "min_population", min);
+        }
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .number_of_cities(country_name, min_population, request_key(&request))
+            .await?;
+
+        let mut response = Response::new(v2::GetNumberOfCitiesResponse {
+            number_of_cities: outcome.value as u64,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn get_number_of_countries(
+        &self,
+        request: Request<v2::GetNumberOfCountriesRequest>,
+    ) -> Result<Response<v2::GetNumberOfCountriesResponse>, Status> {
+        println!("[INFO] Request to get number of countries (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let city_count = request.get_ref().city_count;
+        let min_population = request.get_ref().min_population;
+        let max_population = request.get_ref().max_population;
+
+        let mut violations = Vec::new();
+        let city_count = check_int64(&mut violations, "city_count", city_count);
+        if let Some(min) = min_population {
+            check_not_negative(&mut violations, "min_population", min);
+        }
+        if let Some(max) = max_population {
+            check_positive(&mut violations, "max_population", max);
+        }
+        if let (Some(min), Some(max)) = (min_population, max_population) {
+            check_range(
+                &mut violations,
+                "max_population",
+                "min_population",
+                max < min,
+            );
+        }
+        reject_invalid(violations)?;
+
+        let outcome = self
+            .number_of_countries(
+                city_count,
+                min_population,
+                max_population,
+                request_key(&request),
+            )
+            .await?;
+
+        let mut response = Response::new(v2::GetNumberOfCountriesResponse {
+            number_of_countries: outcome.value as u64,
+        });
+
+        self.insert_metadata(
+            &mut response,
+            start,
+            &in_flight,
+            outcome.cache_hit,
+            outcome.pool_wait,
+        );
+
+        Ok(response)
+    }
+
+    async fn find_cities_within_radius(
+        &self,
+        request: Request<v2::FindCitiesWithinRadiusRequest>,
+    ) -> Result<Response<v2::FindCitiesWithinRadiusResponse>, Status> {
+        println!("[INFO] Request to find cities within a radius (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let message = request.get_ref();
+        let mut violations = Vec::new();
+        let (latitude, longitude) = check_point(&mut violations, "center", message.center.as_ref());
+        if !(message.radius_km.is_finite() && message.radius_km > 0.0) {
+            violations.push((
+                String::from("radius_km"),
+                String::from("must be greater than 0"),
+            ));
+        }
+        let population =
+            check_population_range(&mut violations, "population", message.population.as_ref());
+        let limit = check_limit(&mut violations, "limit", message.limit);
+        reject_invalid(violations)?;
+
+        // Find the cities in the index, and read the records of those within the limit
+        let index = self.geo_index().await?;
+        let mut found = index.within_radius(latitude, longitude, message.radius_km, population);
+        let total_matches = found.len() as u64;
+        found.truncate(limit);
+
+        let ids = found.iter().map(|found| found.city.geoname_id).collect();
+        let records = self.cities_by_id(ids, request_key(&request)).await?;
+
+        let mut response = Response::new(v2::FindCitiesWithinRadiusResponse {
+            cities: city_distances(&found, records.value),
+            total_matches,
+        });
+
+        self.insert_metadata(&mut response, start, &in_flight, false, records.wait);
+
+        Ok(response)
+    }
+
+    async fn find_nearest_cities(
+        &self,
+        request: Request<v2::FindNearestCitiesRequest>,
+    ) -> Result<Response<v2::FindNearestCitiesResponse>, Status> {
+        println!("[INFO] Request to find the nearest cities (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let message = request.get_ref();
+        let mut violations = Vec::new();
+        let (latitude, longitude) = check_point(&mut violations, "point", message.point.as_ref());
+        let k = check_limit(&mut violations, "k", Some(message.k));
+        let population =
+            check_population_range(&mut violations, "population", message.population.as_ref());
+        reject_invalid(violations)?;
+
+        let index = self.geo_index().await?;
+        let found = index.nearest(latitude, longitude, k, population);
+
+        let ids = found.iter().map(|found| found.city.geoname_id).collect();
+        let records = self.cities_by_id(ids, request_key(&request)).await?;
+
+        let mut response = Response::new(v2::FindNearestCitiesResponse {
+            cities: city_distances(&found, records.value),
+        });
+
+        self.insert_metadata(&mut response, start, &in_flight, false, records.wait);
+
+        Ok(response)
+    }
+
+    async fn find_cities_in_bounding_box(
+        &self,
+        request: Request<v2::FindCitiesInBoundingBoxRequest>,
+    ) -> Result<Response<v2::FindCitiesInBoundingBoxResponse>, Status> {
+        println!("[INFO] Request to find cities in a bounding box (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let message = request.get_ref();
+        let mut violations = Vec::new();
+        let (south, west) = check_point(&mut violations, "south_west", message.south_west.as_ref());
+        let (north, east) = check_point(&mut violations, "north_east", message.north_east.as_ref());
+        check_range(
+            &mut violations,
+            "north_east.latitude",
+            "south_west.latitude",
+            north < south,
+        );
+        let population =
+            check_population_range(&mut violations, "population", message.population.as_ref());
+        let limit = check_limit(&mut violations, "limit", message.limit);
+        reject_invalid(violations)?;
+
+        let index = self.geo_index().await?;
+        let mut found = index.in_bounding_box(south, west, north, east, population);
+        let total_matches = found.len() as u64;
+        found.truncate(limit);
+
+        let ids = found.iter().map(|city| city.geoname_id).collect();
+        let mut records = self.cities_by_id(ids, request_key(&request)).await?;
+        let cities = found
+            .iter()
+            .filter_map(|city| records.value.remove(&city.geoname_id))
+            .map(city_message)
+            .collect();
+
+        let mut response = Response::new(v2::FindCitiesInBoundingBoxResponse {
+            cities,
+            total_matches,
+        });
+
+        self.insert_metadata(&mut response, start, &in_flight, false, records.wait);
+
+        Ok(response)
+    }
+
+    async fn rank_cities(
+        &self,
+        request: Request<v2::RankCitiesRequest>,
+    ) -> Result<Response<v2::RankCitiesResponse>, Status> {
+        println!("[INFO] Request to rank cities (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let message = request.get_ref();
+        let mut violations = Vec::new();
+        let order: v2::CityOrder = check_enum(&mut violations, "order", message.order);
+        let direction: v2::SortDirection =
+            check_enum(&mut violations, "direction", message.direction);
+        let (limit, offset) = check_page(&mut violations, message.page.as_ref());
+        if let Some(country) = &message.country {
+            check_country(&mut violations, country);
+        }
+        let population =
+            check_population_range(&mut violations, "population", message.population.as_ref());
+        reject_invalid(violations)?;
+
+        // The order is one of a fixed set of columns, and every value is a parameter
+        let column = match order {
+            v2::CityOrder::Population => "[Population]",
+            v2::CityOrder::Name => "[Name]",
+            v2::CityOrder::Elevation => "[Elevation]",
+        };
+        let filter = "WHERE (?1 IS NULL OR [Country name EN] = ?1) AND (?2 IS NULL OR [Population] >= ?2) AND (?3 IS NULL OR [Population] <= ?3)";
+        let count_query = format!("SELECT COUNT(*) FROM cities {}", filter);
+        let page_query = format!(
+            "{} {} ORDER BY {} {} NULLS LAST, [Geoname ID] LIMIT ?4 OFFSET ?5",
+            SELECT_CITIES,
+            filter,
+            column,
+            sort_keyword(direction)
+        );
+
+        let country = message.country.clone();
+        let params = vec![
+            Value::from(country.clone()),
+            Value::from(population.min),
+            Value::from(population.max),
+        ];
+
+        let result = self
+            .queries
+            .run(request_key(&request), move |connection| {
+                let total_matches: i64 = connection
+                    .prepare_cached(&count_query)?
+                    .query_row(params_from_iter(params.iter()), |row| row.get(0))?;
+
+                let page_params = params
+                    .into_iter()
+                    .chain([Value::from(limit as i64), Value::from(offset)]);
+                let cities = connection
+                    .prepare_cached(&page_query)?
+                    .query_map(params_from_iter(page_params), City::from_row)?
+                    .collect::<rusqlite::Result<Vec<City>>>()?;
+
+                // Tell an unknown country from a country without matching cities
+                let known_country = match &country {
+                    Some(country) if total_matches == 0 => connection
+                        .prepare_cached(
+                            "SELECT EXISTS(SELECT 1 FROM cities WHERE [Country name EN] = ?1)",
+                        )?
+                        .query_row([country], |row| row.get(0))?,
+                    _ => true,
+                };
+
+                Ok((total_matches as u64, cities, known_country))
+            })
+            .await?;
+
+        let (total_matches, cities, known_country) = result.value;
+        if !known_country {
+            let country = message.country.clone().unwrap_or_default();
+            return Err(StatError::CountryNotFound(country).into());
+        }
+
+        let returned = cities.len();
+        let mut response = Response::new(v2::RankCitiesResponse {
+            cities: cities
+                .into_iter()
+                .enumerate()
+                .map(|(index, city)| v2::RankedCity {
+                    rank: offset as u64 + index as u64 + 1,
+                    city: Some(city_message(city)),
+                })
+                .collect(),
+            total_matches,
+            next_offset: next_offset(offset, returned, total_matches),
+        });
+
+        self.insert_metadata(&mut response, start, &in_flight, false, result.wait);
+
+        Ok(response)
+    }
+
+    async fn rank_countries(
+        &self,
+        request: Request<v2::RankCountriesRequest>,
+    ) -> Result<Response<v2::RankCountriesResponse>, Status> {
+        println!("[INFO] Request to rank countries (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let message = request.get_ref();
+        let mut violations = Vec::new();
+        let order: v2::CountryOrder = check_enum(&mut violations, "order", message.order);
+        let direction: v2::SortDirection =
+            check_enum(&mut violations, "direction", message.direction);
+        let (limit, offset) = check_page(&mut violations, message.page.as_ref());
+        let population =
+            check_population_range(&mut violations, "population", message.population.as_ref());
+        let min_city_count = message
+            .min_city_count
+            .map(|count| check_int64(&mut violations, "min_city_count", count));
+        reject_invalid(violations)?;
+
+        // The order is one of a fixed set of columns, and every value is a parameter
+        let column = match order {
+            v2::CountryOrder::Population => "population",
+            v2::CountryOrder::CityCount => "city_count",
+            v2::CountryOrder::Name => "[Country name EN]",
+        };
+        let filter = "GROUP BY [Country name EN] HAVING (?1 IS NULL OR SUM([Population]) >= ?1) AND (?2 IS NULL OR SUM([Population]) <= ?2) AND (?3 IS NULL OR COUNT(*) >= ?3)";
+        let count_query = format!("SELECT COUNT(*) FROM (SELECT 1 FROM cities {})", filter);
+        let page_query = format!(
+            "SELECT [Country name EN], MIN([Country Code]), COUNT(*) AS city_count, SUM([Population]) AS population, MIN([Population]), MAX([Population]) FROM cities {} ORDER BY {} {}, [Country name EN] LIMIT ?4 OFFSET ?5",
+            filter,
+            column,
+            sort_keyword(direction)
+        );
+
+        let params = vec![
+            Value::from(population.min),
+            Value::from(population.max),
+            Value::from(min_city_count),
+        ];
+
+        let result = self
+            .queries
+            .run(request_key(&request), move |connection| {
+                let total_matches: i64 = connection
+                    .prepare_cached(&count_query)?
+                    .query_row(params_from_iter(params.iter()), |row| row.get(0))?;
+
+                let page_params = params
+                    .into_iter()
+                    .chain([Value::from(limit as i64), Value::from(offset)]);
+                let countries = connection
+                    .prepare_cached(&page_query)?
+                    .query_map(params_from_iter(page_params), |row| {
+                        Ok(v2::Country {
+                            name: row.get(0)?,
+                            country_code: row.get(1)?,
+                            city_count: row.get::<_, i64>(2)? as u64,
+                            population: row.get(3)?,
+                            smallest_city_population: row.get(4)?,
+                            largest_city_population: row.get(5)?,
+                        })
+                    })?
+                    .collect::<rusqlite::Result<Vec<v2::Country>>>()?;
+
+                Ok((total_matches as u64, countries))
+            })
+            .await?;
+
+        let (total_matches, countries) = result.value;
+        let returned = countries.len();
+        let mut response = Response::new(v2::RankCountriesResponse {
+            countries: countries
+                .into_iter()
+                .enumerate()
+                .map(|(index, country)| v2::RankedCountry {
+                    rank: offset as u64 + index as u64 + 1,
+                    country: Some(country),
+                })
+                .collect(),
+            total_matches,
+            next_offset: next_offset(offset, returned, total_matches),
+        });
+
+        self.insert_metadata(&mut response, start, &in_flight, false, result.wait);
+
+        Ok(response)
+    }
+
+    type ListCitiesStream = ReceiverStream<Result<v2::ListCitiesResponse, Status>>;
+
+    async fn list_cities(
+        &self,
+        request: Request<v2::ListCitiesRequest>,
+    ) -> Result<Response<Self::ListCitiesStream>, Status> {
+        println!("[INFO] Request to list cities (v2)");
+
+        let start = Instant::now();
+        let in_flight = self.begin_request()?;
+
+        let message = request.get_ref();
+        let mut violations = Vec::new();
+        if let Some(country) = &message.country {
+            check_country(&mut violations, country);
+        }
+        if message
+            .admin1_code
+            .as_ref()
+            .is_some_and(|code| code.trim().is_empty())
+        {
+            violations.push((
+                String::from("admin1_code"),
+                String::from("must not be empty"),
+            ));
+        }
+        let population =
+            check_population_range(&mut violations, "population", message.population.as_ref());
+        let batch_size = check_limit(&mut violations, "batch_size", message.batch_size);
+        reject_invalid(violations)?;
+
+        // Unknown countries are rejected before the stream starts
+        let mut pool_wait = Duration::ZERO;
+        if let Some(country) = message.country.clone() {
+            let result = self
+                .queries
+                .run(request_key(&request), move |connection| {
+                    connection
+                        .prepare_cached(
+                            "SELECT EXISTS(SELECT 1 FROM cities WHERE [Country name EN] = ?1)",
+                        )?
+                        .query_row([country], |row| row.get::<_, bool>(0))
+                })
+                .await?;
+            if !result.value {
+                let country = message.country.clone().unwrap_or_default();
+                return Err(StatError::CountryNotFound(country).into());
+            }
+            pool_wait = result.wait;
+        }
+
+        let filter = CityFilter {
+            country: message.country.clone(),
+            population,
+            admin1_code: message.admin1_code.clone(),
+        };
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let mut response = Response::new(ReceiverStream::new(receiver));
+        self.insert_metadata(&mut response, start, &in_flight, false, pool_wait);
+
+        // The request stays in flight until the last batch is sent
+        tokio::spawn(stream_cities(
+            self.queries.clone(),
+            filter,
+            batch_size,
+            request_key(&request),
+            sender,
+            in_flight,
+        ));
+
+        Ok(response)
+    }
+}
+
+/// Options of the server given as command-line flags.
+pub struct ServerOptions {
+    /// Path to the file with the zones and servers of the simulation.
+    topology_path: String,
+    /// Capacity and eviction policy of the result cache. Disabled if not set.
+    cache: Option<(usize, EvictionPolicy)>,
+    /// Configuration of the database connection pool.
+    pool: PoolConfig,
+    /// Inject the simulated network latency of the topology on the server.
+    latency: bool,
+    /// Requests in flight beyond which new requests are rejected. Unlimited if not set.
+    max_in_flight: Option<usize>,
+}
+
+/// Parse the optional server flags.
+///
+/// `--topology <path>` loads the zones and servers from the given file instead of `topology.toml`.
+/// `--cache <capacity>` enables the result cache, with the eviction policy from `--cache-policy <lru | lfu | ttl:<seconds>>`.
+/// `--pool-size <connections>` and `--pool-timeout <ms>` configure the database connection pool.
+/// `--max-in-flight <requests>` rejects requests with `UNAVAILABLE` while the server has that many in flight.
+/// `--latency` injects the simulated network latency on the server, for clients that do not inject it themselves.
+/// `--inline-queries` runs the queries on the request task instead of a blocking thread, for the deterministic simulation.
+pub fn parse_options(flags: &[String]) -> Result<ServerOptions, Box<dyn std::error::Error>> {
+    let mut cache_capacity: Option<usize> = None;
+    let mut cache_policy = EvictionPolicy::Lru;
+    let mut pool = PoolConfig::default();
+    let mut topology_path = String::from(DEFAULT_TOPOLOGY_PATH);
+    let mut latency = false;
+    let mut max_in_flight: Option<usize> = None;
+
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        // Flags without a value
+        if flag == "--latency" {
+            latency = true;
+            continue;
+        }
+        if flag == "--inline-queries" {
+            pool.inline_queries = true;
+            continue;
+        }
+
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for flag {}", flag))?;
+
+        match flag.as_str() {
+            "--topology" => topology_path = value.clone(),
+            "--cache" => cache_capacity = Some(value.parse::<usize>()?),
+            "--cache-policy" => cache_policy = value.parse::<EvictionPolicy>()?,
+            "--pool-size" => pool.size = value.parse::<usize>()?,
+            "--pool-timeout" => pool.acquire_timeout = Duration::from_millis(value.parse::<u64>()?),
+            "--max-in-flight" => max_in_flight = Some(value.parse::<usize>()?),
+            unknown => return Err(format!("Unknown flag: {}", unknown).into()),
+        }
+    }
+
+    if pool.size == 0 {
+        return Err("The connection pool needs at least one connection".into());
+    }
+
+    Ok(ServerOptions {
+        topology_path,
+        cache: cache_capacity.map(|capacity| (capacity, cache_policy)),
+        pool,
+        latency,
+        max_in_flight,
+    })
+}
+
+/// Run the server with the given id in the topology until the shutdown future completes.
+///
+/// The server listens on its address from the topology, over TCP or on the in-memory network if one is given.
+pub async fn run_server(
+    server_id: u32,
+    options: ServerOptions,
+    network: Option<Arc<MemoryNetwork>>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Get the server address from the topology
+    let topology = Topology::load(&options.topology_path)?;
+    let entry = topology
+        .server(server_id)
+        .ok_or_else(|| format!("Server {} is not defined in the topology", server_id))?;
+    let addr = entry.addr.clone();
+    let server_addr = addr.parse::<SocketAddr>()?;
+
+    // Open the database connections
+    println!(
+        "[INFO] Opening {} database connections (acquire timeout {} ms)",
+        options.pool.size,
+        options.pool.acquire_timeout.as_millis()
+    );
+    let pool = ConnectionPool::open(options.pool)?;
+
+    // Server creation, with a result cache if configured
+    let mut server: StatServer = StatServer::new(server_id, entry.zone, pool);
+    if let Some((capacity, policy)) = options.cache {
+        println!(
+            "[INFO] Caching up to {} results with policy {:?}",
+            capacity, policy
+        );
+        server = server.with_cache(capacity, policy);
+    }
+
+    if let Some(max_in_flight) = options.max_in_flight {
+        println!(
+            "[INFO] Rejecting requests beyond {} in flight",
+            max_in_flight
+        );
+        server = server.with_max_in_flight(max_in_flight);
+    }
+
+    // Simulated service time of the queries
+    if topology.service_time.is_enabled() {
+        let model = ServiceTimeModel::new(topology.service_time.clone());
+        println!("[INFO] Simulating service times with seed {}", model.seed());
+        server = server.with_service_time(model);
+    }
+
+    // Version 1 and 2 of the service share the server, with its cache and connection pool
+    let server = Arc::new(server);
+
+    // Simulated network latency between the client zone and the zone of the server
+    let latency_layer = options.latency.then(|| {
+        let model = LatencyModel::new(topology.latency.clone());
+        println!(
+            "[INFO] Injecting network latency with seed {}",
+            model.seed()
+        );
+        LatencyLayer::server(Arc::new(model), entry.zone)
+    });
+
+    // Health service, reporting both versions of the statistics service as serving once the server listens
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_serving::<StatMethodsServer<StatServer>>()
+        .await;
+    health_reporter
+        .set_serving::<StatMethodsV2Server<StatServer>>()
+        .await;
+
+    // Logging that the server has started
+    println!(
+        "[INFO] Server {} started in zone {} on {}",
+        server_id, entry.zone, addr
+    );
+
+    let router = Server::builder()
+        .layer(option_layer(latency_layer))
+        .add_service(health_service)
+        .add_service(StatMethodsServer::from_arc(server.clone()))
+        .add_service(StatMethodsV2Server::from_arc(server.clone()));
+    let incoming = network.map(|network| network.bind(&addr)).transpose()?;
+
+    // Invalidate the caches on SIGHUP while serving
+    let hangup = tokio::spawn(invalidate_on_hangup(server));
+    let served = match incoming {
+        Some(incoming) => {
+            router
+                .serve_with_incoming_shutdown(incoming, shutdown)
+                .await
+        }
+        None => router.serve_with_shutdown(server_addr, shutdown).await,
+    };
+    hangup.abort();
+    served?;
+
+    println!("[INFO] Server {} stopped", server_id);
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+/// Distribution of a set of measurements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Summary {
+    /// Summarize the values, or `None` if there are no values.
+    pub fn from_values(values: &[u64]) -> Option<Summary> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable();
+
+        let sum: u64 = sorted.iter().sum();
+
+        Some(Summary {
+            count: sorted.len(),
+            min: sorted[0],
+            mean: sum as f64 / sorted.len() as f64,
+            p50: percentile(&sorted, 50.0),
+            p95: percentile(&sorted, 95.0),
+            p99: percentile(&sorted, 99.0),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {}, mean {:.1}, p50 {}, p95 {}, p99 {}, max {}",
+            self.min, self.mean, self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
+
+/// Get the `p`th percentile of sorted values using the nearest rank method.
+///
+/// Panics if `sorted` is empty.
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Get the `q` quantile (0 to 1) of sorted values, interpolating linearly between the nearest values.
+///
+/// Panics if `sorted` is empty.
+pub fn quantile(sorted: &[u64], q: f64) -> f64 {
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - lower as f64;
+    sorted[lower] as f64 + (sorted[upper] as f64 - sorted[lower] as f64) * fraction
+}
+
+/// Quartiles and whiskers of a box plot.
+///
+/// The whiskers reach the furthest values within 1.5 times the interquartile range of the box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxStats {
+    pub lower_whisker: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub upper_whisker: f64,
+}
+
+impl BoxStats {
+    /// Compute the box of the values, or `None` if there are no values.
+    pub fn from_values(values: &[u64]) -> Option<BoxStats> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable();
+
+        let q1 = quantile(&sorted, 0.25);
+        let q3 = quantile(&sorted, 0.75);
+        let reach = 1.5 * (q3 - q1);
+        let lower_whisker = sorted
+            .iter()
+            .map(|value| *value as f64)
+            .find(|value| *value >= q1 - reach)
+            .unwrap_or(q1);
+        let upper_whisker = sorted
+            .iter()
+            .rev()
+            .map(|value| *value as f64)
+            .find(|value| *value <= q3 + reach)
+            .unwrap_or(q3);
+
+        Some(BoxStats {
+            lower_whisker,
+            q1,
+            median: quantile(&sorted, 0.5),
+            q3,
+            upper_whisker,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let sorted: Vec<u64> = (1..=100).collect();
+
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 95.0), 95);
+        assert_eq!(percentile(&sorted, 99.5), 100);
+        assert_eq!(percentile(&sorted, 0.0), 1);
+        assert_eq!(percentile(&[7], 99.0), 7);
+        assert_eq!(percentile(&[1, 2, 3, 4], 50.0), 2);
+    }
+
+    #[test]
+    fn quantiles_interpolate_between_values() {
+        let sorted = [10, 20, 30, 40];
+
+        assert_eq!(quantile(&sorted, 0.0), 10.0);
+        assert_eq!(quantile(&sorted, 0.5), 25.0);
+        assert_eq!(quantile(&sorted, 0.25), 17.5);
+        assert_eq!(quantile(&sorted, 1.0), 40.0);
+        assert_eq!(quantile(&sorted, 2.0), 40.0);
+        assert_eq!(quantile(&[5], 0.75), 5.0);
+    }
+
+    #[test]
+    fn summarizes_unsorted_values() {
+        let summary = Summary::from_values(&[30, 10, 20, 40]).unwrap();
+
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.min, 10);
+        assert_eq!(summary.max, 40);
+        assert_eq!(summary.mean, 25.0);
+        assert_eq!(summary.p50, 20);
+        assert_eq!(summary.p99, 40);
+        assert_eq!(Summary::from_values(&[]), None);
+    }
+
+    #[test]
+    fn whiskers_stop_at_outliers() {
+        let mut values: Vec<u64> = (1..=9).collect();
+        values.push(100);
+        let stats = BoxStats::from_values(&values).unwrap();
+
+        assert_eq!(stats.q1, 3.25);
+        assert_eq!(stats.median, 5.5);
+        assert_eq!(stats.q3, 7.75);
+        assert_eq!(stats.lower_whisker, 1.0);
+        // 100 is beyond 1.5 times the interquartile range above the box
+        assert_eq!(stats.upper_whisker, 9.0);
+        assert_eq!(BoxStats::from_values(&[]), None);
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, LogNormal, Pareto, Uniform};
+
+use crate::dataset::City;
+
+/// Syllables combined into country and city names.
+const SYLLABLES: [&str; 24] = [
+    "ba", "ko", "ri", "sa", "lin", "dor", "ve", "ta", "mar", "nu", "el", "os", "ka", "ren", "bi",
+    "zu", "fa", "lo", "gri", "an", "ste", "mo", "ul", "ph",
+];
+
+/// Distribution of the city populations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PopulationDistribution {
+    /// Uniform between `min` and `max`.
+    Uniform { min: u64, max: u64 },
+    /// Log-normal, where `mu` and `sigma` are the mean and standard deviation of the natural log of the population.
+    LogNormal { mu: f64, sigma: f64 },
+    /// Pareto with the given scale (smallest population) and shape, giving a few very large cities.
+    Pareto { scale: f64, shape: f64 },
+}
+
+impl FromStr for PopulationDistribution {
+    type Err = String;
+
+    /// Parse a distribution from `uniform:<min>:<max>`, `log_normal:<mu>:<sigma>` or `pareto:<scale>:<shape>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid population distribution: {}", s);
+
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+        let a = parts[1].parse::<f64>().map_err(|_| invalid())?;
+        let b = parts[2].parse::<f64>().map_err(|_| invalid())?;
+
+        let distribution = match parts[0].to_lowercase().as_str() {
+            "uniform" => PopulationDistribution::Uniform {
+                min: a as u64,
+                max: b as u64,
+            },
+            "log_normal" => PopulationDistribution::LogNormal { mu: a, sigma: b },
+            "pareto" => PopulationDistribution::Pareto { scale: a, shape: b },
+            _ => return Err(format!("Unknown population distribution: {}", s)),
+        };
+
+        let valid = match distribution {
+            PopulationDistribution::Uniform { min, max } => min <= max,
+            // rand_distr accepts a negative standard deviation
+            PopulationDistribution::LogNormal { mu, sigma } => {
+                sigma >= 0.0 && LogNormal::new(mu, sigma).is_ok()
+            }
+            PopulationDistribution::Pareto { scale, shape } => Pareto::new(scale, shape).is_ok(),
+        };
+        if valid {
+            Ok(distribution)
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl PopulationDistribution {
+    fn sample<R: Rng>(&self, rng: &mut R) -> i64 {
+        let population = match *self {
+            PopulationDistribution::Uniform { min, max } => {
+                Uniform::new_inclusive(min, max).sample(rng) as f64
+            }
+            PopulationDistribution::LogNormal { mu, sigma } => {
+                LogNormal::new(mu, sigma).unwrap().sample(rng)
+            }
+            PopulationDistribution::Pareto { scale, shape } => {
+                Pareto::new(scale, shape).unwrap().sample(rng)
+            }
+        };
+        population.clamp(0.0, i64::MAX as f64) as i64
+    }
+}
+
+/// Configuration of a synthetic cities dataset.
+#[derive(Debug, Clone)]
+pub struct SyntheticConfig {
+    pub seed: u64,
+    /// Number of countries, at most 676 so each gets a two letter code.
+    pub countries: usize,
+    /// Smallest and largest number of cities in a country.
+    pub cities_per_country: (usize, usize),
+    pub population: PopulationDistribution,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        SyntheticConfig {
+            seed: 42,
+            countries: 10,
+            cities_per_country: (50, 500),
+            population: PopulationDistribution::Pareto {
+                scale: 1000.0,
+                shape: 1.1,
+            },
+        }
+    }
+}
+
+/// A generated dataset: the country names and every city.
+pub struct SyntheticDataset {
+    pub countries: Vec<String>,
+    pub cities: Vec<City>,
+}
+
+impl SyntheticDataset {
+    /// Generate the dataset. The same configuration always gives the same dataset.
+    pub fn generate(config: &SyntheticConfig) -> Result<Self, String> {
+        let (min_cities, max_cities) = config.cities_per_country;
+        if config.countries == 0 || config.countries > 26 * 26 {
+            return Err(String::from(
+                "The number of countries must be from 1 to 676",
+            ));
+        }
+        if min_cities > max_cities {
+            return Err(String::from(
+                "The smallest number of cities per country is larger than the largest",
+            ));
+        }
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let mut names = HashSet::new();
+        let mut countries = Vec::with_capacity(config.countries);
+        let mut cities = Vec::new();
+
+        for index in 0..config.countries {
+            let country = unique_name(&mut rng, &mut names, 2);
+            let country_code = country_code(index);
+
+            // Cities are spread around a center, like in a real country
+            let center_latitude = rng.gen_range(-60.0..60.0);
+            let center_longitude = rng.gen_range(-170.0..170.0);
+
+            for _ in 0..rng.gen_range(min_cities..=max_cities) {
+                let name = unique_name(&mut rng, &mut names, 3);
+                cities.push(City {
+                    geoname_id: cities.len() as i64 + 1,
+                    ascii_name: name.clone(),
+                    name,
+                    country_code: country_code.clone(),
+                    country: country.clone(),
+                    admin1_code: Some(format!("{:02}", rng.gen_range(1..=10))),
+                    population: config.population.sample(&mut rng),
+                    elevation: Some(rng.gen_range(0..2000)),
+                    timezone: Some(String::from("Etc/UTC")),
+                    latitude: center_latitude + rng.gen_range(-5.0..5.0),
+                    longitude: center_longitude + rng.gen_range(-5.0..5.0),
+                });
+            }
+
+            countries.push(country);
+        }
+
+        Ok(SyntheticDataset { countries, cities })
+    }
+
+    /// Populations of every city, sorted from smallest to largest.
+    pub fn sorted_populations(&self) -> Vec<i64> {
+        let mut populations: Vec<i64> = self.cities.iter().map(|city| city.population).collect();
+        populations.sort_unstable();
+        populations
+    }
+}
+
+/// Build a name from random syllables, not used before.
+///
+/// Names are a single word, since request files separate values with spaces.
+fn unique_name(rng: &mut StdRng, used: &mut HashSet<String>, syllables: usize) -> String {
+    let mut name = String::new();
+    for _ in 0..syllables {
+        name.push_str(SYLLABLES[rng.gen_range(0..SYLLABLES.len())]);
+    }
+    // Add syllables until the name is unique
+    while used.contains(&name) {
+        name.push_str(SYLLABLES[rng.gen_range(0..SYLLABLES.len())]);
+    }
+    used.insert(name.clone());
+
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// Two letter code of the country with the given index: `AA`, `AB`, ...
+fn country_code(index: usize) -> String {
+    let letter = |i: usize| (b'A' + i as u8) as char;
+    format!("{}{}", letter(index / 26), letter(index % 26))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(seed: u64) -> SyntheticConfig {
+        SyntheticConfig {
+            seed,
+            countries: 5,
+            cities_per_country: (20, 40),
+            ..SyntheticConfig::default()
+        }
+    }
+
+    /// The generated values of every city, to compare datasets.
+    fn rows(dataset: &SyntheticDataset) -> Vec<(i64, String, String, String, i64, f64, f64)> {
+        dataset
+            .cities
+            .iter()
+            .map(|city| {
+                (
+                    city.geoname_id,
+                    city.name.clone(),
+                    city.country_code.clone(),
+                    city.country.clone(),
+                    city.population,
+                    city.latitude,
+                    city.longitude,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_dataset() {
+        let first = SyntheticDataset::generate(&config(7)).unwrap();
+        let second = SyntheticDataset::generate(&config(7)).unwrap();
+        let other = SyntheticDataset::generate(&config(8)).unwrap();
+
+        assert_eq!(first.countries, second.countries);
+        assert_eq!(rows(&first), rows(&second));
+        assert_ne!(rows(&first), rows(&other));
+    }
+
+    #[test]
+    fn generates_unique_names_and_cities_per_country_in_range() {
+        let dataset = SyntheticDataset::generate(&config(3)).unwrap();
+        assert_eq!(dataset.countries.len(), 5);
+
+        let mut names: HashSet<&str> = dataset.countries.iter().map(String::as_str).collect();
+        for city in &dataset.cities {
+            assert!(names.insert(&city.name), "{} is not unique", city.name);
+            assert!(!city.name.contains(' '));
+        }
+
+        for (index, country) in dataset.countries.iter().enumerate() {
+            let cities: Vec<&City> = dataset
+                .cities
+                .iter()
+                .filter(|city| &city.country == country)
+                .collect();
+            assert!((20..=40).contains(&cities.len()), "{}", cities.len());
+            assert!(cities
+                .iter()
+                .all(|city| city.country_code == country_code(index)));
+        }
+
+        let ids: Vec<i64> = dataset.cities.iter().map(|city| city.geoname_id).collect();
+        assert_eq!(ids, (1..=dataset.cities.len() as i64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn populations_follow_the_distribution() {
+        let mut uniform = config(5);
+        uniform.population = PopulationDistribution::Uniform {
+            min: 1000,
+            max: 2000,
+        };
+        let populations = SyntheticDataset::generate(&uniform)
+            .unwrap()
+            .sorted_populations();
+        assert!(populations.iter().all(|p| (1000..=2000).contains(p)));
+        let median = populations[populations.len() / 2];
+        assert!((1300..=1700).contains(&median), "median {}", median);
+
+        // Pareto gives a long tail above the scale
+        let populations = SyntheticDataset::generate(&config(5))
+            .unwrap()
+            .sorted_populations();
+        assert!(populations[0] >= 1000);
+        let median = populations[populations.len() / 2];
+        assert!(*populations.last().unwrap() > 10 * median);
+    }
+
+    #[test]
+    fn parses_population_distributions() {
+        assert_eq!(
+            "uniform:10:20".parse::<PopulationDistribution>(),
+            Ok(PopulationDistribution::Uniform { min: 10, max: 20 })
+        );
+        assert_eq!(
+            "log_normal:9:1.5".parse::<PopulationDistribution>(),
+            Ok(PopulationDistribution::LogNormal {
+                mu: 9.0,
+                sigma: 1.5
+            })
+        );
+        for invalid in [
+            "uniform:20:10",
+            "pareto:0:1",
+            "log_normal:9:-1",
+            "pareto:1000",
+            "zipf:1:2",
+        ] {
+            assert!(
+                invalid.parse::<PopulationDistribution>().is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_configurations() {
+        for (countries, cities_per_country) in [(0, (1, 2)), (677, (1, 2)), (3, (5, 4))] {
+            let config = SyntheticConfig {
+                countries,
+                cities_per_country,
+                ..SyntheticConfig::default()
+            };
+            assert!(SyntheticDataset::generate(&config).is_err());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+
+use crate::faults::FaultConfig;
+use crate::latency::{LatencyConfig, ServiceTimeConfig};
+
+/// Path of the topology file used when none is given.
+pub const DEFAULT_TOPOLOGY_PATH: &str = "topology.toml";
+
+/// Layout of the simulated system: the zones, the servers in each zone and the proxy.
+///
+/// Loaded from a TOML file shared by the server, client and proxy binaries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Topology {
+    /// Address the proxy listens on.
+    pub proxy: Option<String>,
+    pub zones: Vec<Zone>,
+    pub servers: Vec<ServerEntry>,
+    /// Simulated network latency between the zones.
+    #[serde(default)]
+    pub latency: LatencyConfig,
+    /// Injected network faults between the zones.
+    #[serde(default)]
+    pub faults: FaultConfig,
+    /// Simulated time the servers spend on each query.
+    #[serde(default)]
+    pub service_time: ServiceTimeConfig,
+}
+
+/// A geographical area with one or more servers.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Zone {
+    pub id: u32,
+    /// Optional readable name of the zone.
+    pub name: Option<String>,
+}
+
+/// A `StatServer` instance and the zone it runs in.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerEntry {
+    pub id: u32,
+    pub zone: u32,
+    /// Socket address the server listens on, without protocol.
+    pub addr: String,
+}
+
+impl ServerEntry {
+    /// Address of the server with protocol, used by clients to connect.
+    pub fn uri(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Topology {
+    /// Load and validate the topology from a TOML file.
+    pub fn load(path: &str) -> Result<Topology, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read topology file {}: {}", path, e))?;
+
+        let topology: Topology = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse topology file {}: {}", path, e))?;
+
+        topology.validate()?;
+        Ok(topology)
+    }
+
+    /// Check that ids are unique, every server is in a known zone, every address is valid and the latency and fault links and the service time are valid.
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut zone_ids = HashSet::new();
+        for zone in &self.zones {
+            if !zone_ids.insert(zone.id) {
+                return Err(format!("Zone {} is defined more than once", zone.id).into());
+            }
+        }
+
+        let mut server_ids = HashSet::new();
+        for server in &self.servers {
+            if !server_ids.insert(server.id) {
+                return Err(format!("Server {} is defined more than once", server.id).into());
+            }
+            if !zone_ids.contains(&server.zone) {
+                return Err(format!(
+                    "Server {} is in zone {}, which is not defined",
+                    server.id, server.zone
+                )
+                .into());
+            }
+            server
+                .addr
+                .parse::<SocketAddr>()
+                .map_err(|e| format!("Invalid address of server {}: {}", server.id, e))?;
+        }
+
+        for zone in &self.zones {
+            if self.servers_in_zone(zone.id).next().is_none() {
+                return Err(format!("Zone {} has no servers", zone.id).into());
+            }
+        }
+
+        self.latency.validate(|zone| zone_ids.contains(&zone))?;
+        self.faults.validate(|zone| zone_ids.contains(&zone))?;
+        self.service_time.validate()?;
+
+        if let Some(proxy) = &self.proxy {
+            proxy
+                .parse::<SocketAddr>()
+                .map_err(|e| format!("Invalid proxy address: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the server with the given id.
+    pub fn server(&self, id: u32) -> Option<&ServerEntry> {
+        self.servers.iter().find(|server| server.id == id)
+    }
+
+    /// Get the zone with the given id.
+    pub fn zone(&self, id: u32) -> Option<&Zone> {
+        self.zones.iter().find(|zone| zone.id == id)
+    }
+
+    /// Iterate over the servers in the given zone.
+    pub fn servers_in_zone(&self, zone: u32) -> impl Iterator<Item = &ServerEntry> {
+        self.servers
+            .iter()
+            .filter(move |server| server.zone == zone)
+    }
+
+    /// Pick a server in the zone, spreading requests round robin with the given counter.
+    ///
+    /// Returns `None` if the zone has no servers.
+    pub fn pick_server(&self, zone: u32, counter: usize) -> Option<&ServerEntry> {
+        let count = self.servers_in_zone(zone).count();
+        if count == 0 {
+            return None;
+        }
+        self.servers_in_zone(zone).nth(counter % count)
+    }
+}
+
+/// Parse a zone given as `Zone:<ID>` in a request file.
+pub fn parse_zone(value: &str) -> Option<u32> {
+    value.strip_prefix("Zone:")?.parse::<u32>().ok()
+}
@@ -0,0 +1,406 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Zipf};
+
+use crate::request_file::{RequestEntry, RequestFileHeader};
+
+/// Function names of the request file format.
+pub const FUNCTIONS: [&str; 4] = [
+    "getPopulationofCountry",
+    "getNumberofCities",
+    "getNumberofCountries",
+    "getNumberofCountriesMax",
+];
+
+/// Countries of the original request files, found in the geonames dataset.
+pub const DEFAULT_COUNTRIES: [&str; 10] = [
+    "Netherlands",
+    "Norway",
+    "Sweden",
+    "Switzerland",
+    "Germany",
+    "Canada",
+    "Australia",
+    "Belgium",
+    "Israel",
+    "Egypt",
+];
+
+/// What the requests of a workload look like.
+#[derive(Debug, Clone)]
+pub struct WorkloadConfig {
+    /// Countries used in the requests, from the most to the least popular.
+    pub countries: Vec<String>,
+    /// Zones the requests are sent to.
+    pub zones: Vec<u32>,
+    /// Relative weights of the functions, in the order of `FUNCTIONS`.
+    pub function_weights: [f64; 4],
+    /// Exponent of the Zipf distribution of the country popularity. With 0 every country is equally popular.
+    pub country_skew: f64,
+    /// Zone of the client. Without a home zone the requests are spread evenly over the zones.
+    pub home_zone: Option<u32>,
+    /// Share of the requests sent to the home zone. The rest are spread evenly over the other zones.
+    pub home_affinity: f64,
+    /// Range of the population thresholds. Must fit in the `int32` fields the client sends.
+    pub population_range: RangeInclusive<u64>,
+    /// Range of the city count thresholds of `getNumberofCountries` and `getNumberofCountriesMax`.
+    pub city_count_range: RangeInclusive<u32>,
+}
+
+impl WorkloadConfig {
+    /// Uniform workload with the parameter ranges of the original request files.
+    pub fn new(countries: Vec<String>, zones: Vec<u32>) -> Self {
+        WorkloadConfig {
+            countries,
+            zones,
+            function_weights: [1.0; 4],
+            country_skew: 0.0,
+            home_zone: None,
+            home_affinity: 0.0,
+            population_range: 100_000..=9_999_999,
+            city_count_range: 1..=10,
+        }
+    }
+
+    /// Check that there is at least one country and zone, that the ranges are not empty and fit in `int32`,
+    /// and that the distributions are valid.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.countries.is_empty() {
+            return Err(String::from("The workload has no countries"));
+        }
+        if self.zones.is_empty() {
+            return Err(String::from("The workload has no zones"));
+        }
+        if self.population_range.is_empty() || self.city_count_range.is_empty() {
+            return Err(String::from("The workload has an empty parameter range"));
+        }
+        if *self.population_range.end() > i32::MAX as u64
+            || *self.city_count_range.end() > i32::MAX as u32
+        {
+            return Err(format!(
+                "The workload parameter ranges must be at most {}",
+                i32::MAX
+            ));
+        }
+        if WeightedIndex::new(self.function_weights).is_err() {
+            return Err(format!(
+                "Invalid function weights: {:?}",
+                self.function_weights
+            ));
+        }
+        if !(self.country_skew.is_finite() && self.country_skew >= 0.0) {
+            return Err(format!("Invalid country skew: {}", self.country_skew));
+        }
+        if !(0.0..=1.0).contains(&self.home_affinity) {
+            return Err(format!(
+                "Invalid home zone affinity: {}",
+                self.home_affinity
+            ));
+        }
+        if let Some(zone) = self.home_zone {
+            if !self.zones.contains(&zone) {
+                return Err(format!("Home zone {} is not in the workload", zone));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generates lines of a request file from a seeded random number generator.
+pub struct RequestGenerator {
+    config: WorkloadConfig,
+    rng: StdRng,
+    functions: WeightedIndex<f64>,
+    countries: Zipf<f64>,
+}
+
+impl RequestGenerator {
+    /// Create a generator for a validated configuration.
+    pub fn new(config: WorkloadConfig, seed: u64) -> Self {
+        RequestGenerator {
+            functions: WeightedIndex::new(config.function_weights)
+                .expect("Workload has invalid function weights"),
+            countries: Zipf::new(config.countries.len() as u64, config.country_skew)
+                .expect("Workload has an invalid country skew"),
+            config,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Generate the next request.
+    pub fn next_entry(&mut self) -> RequestEntry {
+        let function = FUNCTIONS[self.functions.sample(&mut self.rng)];
+        let mut entry = RequestEntry {
+            rpc: function.to_string(),
+            zone: self.zone(),
+            ..Default::default()
+        };
+
+        match function {
+            "getPopulationofCountry" => entry.country = Some(self.country()),
+            "getNumberofCities" => {
+                entry.country = Some(self.country());
+                entry.min_population = Some(self.population());
+            }
+            "getNumberofCountries" => {
+                entry.city_count = Some(self.city_count());
+                entry.min_population = Some(self.population());
+            }
+            _ => {
+                let (min, max) = (self.population(), self.population());
+                entry.city_count = Some(self.city_count());
+                entry.min_population = Some(min.min(max));
+                entry.max_population = Some(min.max(max));
+            }
+        }
+
+        entry
+    }
+
+    /// Generate the next request as a line of the text format.
+    pub fn next_line(&mut self) -> String {
+        self.next_entry()
+            .to_inputs()
+            .expect("Generated request is missing a parameter")
+            .join(" ")
+    }
+
+    /// Pick the zone, preferring the home zone by the configured affinity.
+    fn zone(&mut self) -> u32 {
+        let Some(home) = self.config.home_zone else {
+            return *self.config.zones.choose(&mut self.rng).unwrap();
+        };
+
+        let others: Vec<u32> = self
+            .config
+            .zones
+            .iter()
+            .copied()
+            .filter(|zone| *zone != home)
+            .collect();
+        if others.is_empty() || self.rng.gen_bool(self.config.home_affinity) {
+            home
+        } else {
+            *others.choose(&mut self.rng).unwrap()
+        }
+    }
+
+    /// Pick a country, where the Zipf rank is the position in the country list.
+    fn country(&mut self) -> String {
+        let rank = self.countries.sample(&mut self.rng) as usize;
+        self.config.countries[rank - 1].clone()
+    }
+
+    fn population(&mut self) -> u64 {
+        self.rng.gen_range(self.config.population_range.clone())
+    }
+
+    fn city_count(&mut self) -> u32 {
+        self.rng.gen_range(self.config.city_count_range.clone())
+    }
+}
+
+/// Format of a written request file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestFormat {
+    /// Whitespace separated text, one request per line.
+    Text,
+    /// Version 2 JSON Lines, with a header line.
+    JsonLines,
+}
+
+impl FromStr for RequestFormat {
+    type Err = String;
+
+    /// Parse a format from `text` or `jsonl`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(RequestFormat::Text),
+            "jsonl" => Ok(RequestFormat::JsonLines),
+            _ => Err(format!("Unknown request file format: {}", s)),
+        }
+    }
+}
+
+impl RequestFormat {
+    /// File extension used for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RequestFormat::Text => "txt",
+            RequestFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// Write a request file with the given number of requests.
+pub fn write_request_file(
+    path: &str,
+    generator: &mut RequestGenerator,
+    requests: usize,
+    format: RequestFormat,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    match format {
+        RequestFormat::Text => {
+            for _ in 0..requests {
+                writeln!(file, "{}", generator.next_line())?;
+            }
+        }
+        RequestFormat::JsonLines => {
+            serde_json::to_writer(&mut file, &RequestFileHeader::default())?;
+            writeln!(file)?;
+            for index in 0..requests {
+                let mut entry = generator.next_entry();
+                entry.id = Some(index.to_string());
+                serde_json::to_writer(&mut file, &entry)?;
+                writeln!(file)?;
+            }
+        }
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 10_000;
+
+    fn config() -> WorkloadConfig {
+        let countries = DEFAULT_COUNTRIES.iter().map(|c| c.to_string()).collect();
+        WorkloadConfig::new(countries, vec![1, 2, 3, 4])
+    }
+
+    /// Number of times each value is returned by the sampler.
+    fn counts<T: std::hash::Hash + Eq>(
+        mut sample: impl FnMut() -> T,
+    ) -> std::collections::HashMap<T, usize> {
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..SAMPLES {
+            *counts.entry(sample()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn same_seed_gives_same_requests() {
+        let lines = |seed| {
+            let mut generator = RequestGenerator::new(config(), seed);
+            (0..100).map(|_| generator.next_line()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(lines(1), lines(1));
+        assert_ne!(lines(1), lines(2));
+    }
+
+    #[test]
+    fn zipf_rank_one_is_the_first_country() {
+        let mut config = config();
+        config.country_skew = 1.5;
+        let mut generator = RequestGenerator::new(config, 3);
+        let counts = counts(|| generator.country());
+
+        // Every rank maps to a country, from the most to the least popular
+        assert_eq!(counts.len(), DEFAULT_COUNTRIES.len());
+        let by_rank: Vec<usize> = DEFAULT_COUNTRIES
+            .iter()
+            .map(|country| counts[&country.to_string()])
+            .collect();
+        assert!(
+            by_rank.windows(2).all(|pair| pair[0] > pair[1]),
+            "{:?}",
+            by_rank
+        );
+        // P(rank 1) is 1 / H(10, 1.5), about 0.50
+        let first = by_rank[0] as f64 / SAMPLES as f64;
+        assert!((0.46..0.54).contains(&first), "{}", first);
+    }
+
+    #[test]
+    fn countries_are_equally_popular_without_skew() {
+        let mut generator = RequestGenerator::new(config(), 4);
+        let counts = counts(|| generator.country());
+
+        assert_eq!(counts.len(), DEFAULT_COUNTRIES.len());
+        let expected = SAMPLES / DEFAULT_COUNTRIES.len();
+        for count in counts.values() {
+            assert!(count.abs_diff(expected) < expected / 5, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn home_zone_gets_its_share_and_the_rest_is_spread() {
+        let mut config = config();
+        config.home_zone = Some(2);
+        config.home_affinity = 0.7;
+        let mut generator = RequestGenerator::new(config, 5);
+        let counts = counts(|| generator.zone());
+
+        let home = counts[&2] as f64 / SAMPLES as f64;
+        assert!((0.67..0.73).contains(&home), "{}", home);
+        for zone in [1, 3, 4] {
+            let share = counts[&zone] as f64 / SAMPLES as f64;
+            assert!((0.08..0.12).contains(&share), "zone {}: {}", zone, share);
+        }
+    }
+
+    #[test]
+    fn affinity_bounds_send_all_or_none_to_the_home_zone() {
+        let zones = |affinity| {
+            let mut config = config();
+            config.home_zone = Some(3);
+            config.home_affinity = affinity;
+            let mut generator = RequestGenerator::new(config, 6);
+            counts(|| generator.zone())
+        };
+
+        assert_eq!(zones(1.0).keys().collect::<Vec<_>>(), [&3]);
+        let zones = zones(0.0);
+        assert!(!zones.contains_key(&3));
+        assert_eq!(zones.len(), 3);
+    }
+
+    #[test]
+    fn requests_follow_the_function_weights_and_ranges() {
+        let mut config = config();
+        config.function_weights = [0.0, 0.0, 0.0, 1.0];
+        config.population_range = 10..=20;
+        config.city_count_range = 2..=3;
+        let mut generator = RequestGenerator::new(config, 7);
+
+        for _ in 0..1000 {
+            let entry = generator.next_entry();
+            assert_eq!(entry.rpc, "getNumberofCountriesMax");
+            let (min, max) = (entry.min_population.unwrap(), entry.max_population.unwrap());
+            assert!(10 <= min && min <= max && max <= 20);
+            assert!((2..=3).contains(&entry.city_count.unwrap()));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_configurations() {
+        let cases: [fn(&mut WorkloadConfig); 7] = [
+            |config| config.zones.clear(),
+            |config| config.countries.clear(),
+            |config| config.population_range = 1..=i32::MAX as u64 + 1,
+            |config| config.function_weights = [0.0; 4],
+            |config| config.country_skew = -1.0,
+            |config| config.home_affinity = 1.5,
+            |config| config.home_zone = Some(9),
+        ];
+
+        for (index, change) in cases.iter().enumerate() {
+            let mut config = config();
+            change(&mut config);
+            assert!(config.validate().is_err(), "case {}", index);
+        }
+        assert!(config().validate().is_ok());
+    }
+}